@@ -22,10 +22,14 @@ sys.stdout = os.fdopen(sys.stdout.fileno(), 'w', 1)
 sys.stderr = os.fdopen(sys.stderr.fileno(), 'w', 1)
 
 
+def available_fund_args():
+    return json.dumps({"token_id": global_config.buyback_token_in_contract}).encode('utf8')
+
+
 def handle_buy_buck_one(random_num):
     try:
         conn = MultiNodeJsonProvider()
-        ret = conn.view_call(global_config.buyback_contract, "get_available_fund_amount", b'')
+        ret = conn.view_call(global_config.buyback_contract, "get_available_fund_amount", available_fund_args())
         b = "".join([chr(x) for x in ret["result"]])
         amount_in = int(json.loads(b))
         print("first fund_amount:", amount_in)
@@ -33,7 +37,7 @@ def handle_buy_buck_one(random_num):
             handle_flow(amount_in, random_num)
             print("Wait for 60 seconds for the second verification")
             time.sleep(60)
-            ret = conn.view_call(global_config.buyback_contract, "get_available_fund_amount", b'')
+            ret = conn.view_call(global_config.buyback_contract, "get_available_fund_amount", available_fund_args())
             b = "".join([chr(x) for x in ret["result"]])
             amount_in = int(json.loads(b))
             print("second fund_amount:", amount_in)
@@ -50,7 +54,7 @@ def handle_buy_buck_one(random_num):
         print("Wait for 60 seconds for the second verification")
         time.sleep(60)
         conn = MultiNodeJsonProvider()
-        ret = conn.view_call(global_config.buyback_contract, "get_available_fund_amount", b'')
+        ret = conn.view_call(global_config.buyback_contract, "get_available_fund_amount", available_fund_args())
         b = "".join([chr(x) for x in ret["result"]])
         amount_in = int(json.loads(b))
         print("second fund_amount:", amount_in)
@@ -63,7 +67,7 @@ def handle_buy_buck_one(random_num):
 def handle_buy_buck_two(random_num):
     try:
         conn = MultiNodeJsonProvider()
-        ret = conn.view_call(global_config.buyback_contract, "get_available_fund_amount", b'')
+        ret = conn.view_call(global_config.buyback_contract, "get_available_fund_amount", available_fund_args())
         b = "".join([chr(x) for x in ret["result"]])
         amount_in = int(json.loads(b))
         print("retry fund_amount:", amount_in)
@@ -71,7 +75,7 @@ def handle_buy_buck_two(random_num):
             handle_flow(amount_in, random_num)
             print("Wait for 60 seconds for the second verification")
             time.sleep(60)
-            ret = conn.view_call(global_config.buyback_contract, "get_available_fund_amount", b'')
+            ret = conn.view_call(global_config.buyback_contract, "get_available_fund_amount", available_fund_args())
             b = "".join([chr(x) for x in ret["result"]])
             amount_in = int(json.loads(b))
             print("retry fund_amount:", amount_in)
@@ -88,7 +92,7 @@ def handle_buy_buck_two(random_num):
         print("Wait for 60 seconds for the second verification")
         time.sleep(60)
         conn = MultiNodeJsonProvider()
-        ret = conn.view_call(global_config.buyback_contract, "get_available_fund_amount", b'')
+        ret = conn.view_call(global_config.buyback_contract, "get_available_fund_amount", available_fund_args())
         b = "".join([chr(x) for x in ret["result"]])
         amount_in = int(json.loads(b))
         print("retry fund_amount:", amount_in)
@@ -109,7 +113,7 @@ def handle_flow(amount_in, random_num):
     list_pools_data_ret = requests.get(url=query_list_pools_url, verify=False)
     pools = json.loads(list_pools_data_ret.text)
 
-    actions = []
+    min_amount_out = 0
     buyback_pool_one = {}
     buyback_pool_two = {}
     for pool in pools:
@@ -118,58 +122,34 @@ def handle_flow(amount_in, random_num):
         if pool["id"] == global_config.buyback_pool_two:
             buyback_pool_two = pool
 
+    # the swaps are built by the contract from the registered route, only min_amount_out is estimated here
     if buyback_pool_one != {} and buyback_pool_two != {}:
         one_account_ids = buyback_pool_one["token_account_ids"]
         one_amounts = buyback_pool_one["amounts"]
         if one_account_ids[0] == global_config.buyback_token_in_contract:
             one_in_balance = one_amounts[0]
             one_out_balance = one_amounts[1]
-            one_token_in = one_account_ids[0]
-            one_token_out = one_account_ids[1]
         else:
             one_in_balance = one_amounts[1]
             one_out_balance = one_amounts[0]
-            one_token_in = one_account_ids[1]
-            one_token_out = one_account_ids[0]
         one_amount_out = get_token_flow_ratio(amount_in, one_in_balance, one_out_balance, buyback_pool_one["total_fee"])
         print("one_amount_out:", one_amount_out)
-        one_min_amount_out = int(decimal_mult(one_amount_out, 0.997))
-        action_one = {
-            "pool_id": int(buyback_pool_one["id"]),
-            "token_in": one_token_in,
-            "amount_in": str(amount_in),
-            "token_out": one_token_out,
-            "min_amount_out": str(one_min_amount_out)
-        }
-        actions.append(action_one)
         two_account_ids = buyback_pool_two["token_account_ids"]
         two_amounts = buyback_pool_two["amounts"]
         if two_account_ids[1] == global_config.buyback_token_out_contract:
             two_in_balance = two_amounts[0]
             two_out_balance = two_amounts[1]
-            two_token_in = two_account_ids[0]
-            two_token_out = two_account_ids[1]
         else:
             two_in_balance = two_amounts[1]
             two_out_balance = two_amounts[0]
-            two_token_in = two_account_ids[1]
-            two_token_out = two_account_ids[0]
         two_amount_out = get_token_flow_ratio(one_amount_out, two_in_balance, two_out_balance,
                                               buyback_pool_two["total_fee"])
         print("two_amount_out:", two_amount_out)
-        two_min_amount_out = int(decimal_mult(two_amount_out, 0.997))
-        action_two = {
-            "pool_id": int(buyback_pool_two["id"]),
-            "token_in": two_token_in,
-            "amount_in": None,
-            "token_out": two_token_out,
-            "min_amount_out": str(two_min_amount_out)
-        }
-        actions.append(action_two)
-    print("actions:", actions)
+        min_amount_out = int(decimal_mult(two_amount_out, 0.997))
+    print("route_id:", global_config.buyback_route_id, "amount_in:", amount_in, "min_amount_out:", min_amount_out)
     signer = globals.get_signer_account(global_config.signer_account_id)
     burrow_handler = RpcHandler(signer, global_config.buyback_contract)
-    ret = burrow_handler.do_buyback(actions)
+    ret = burrow_handler.execute_registered_buyback(global_config.buyback_route_id, amount_in, min_amount_out)
     # print("buyback:", ret)
     return ret
 
@@ -1,21 +1,16 @@
-import json
-
-
 class RpcHandler:
     def __init__(self, signer, contract_id):
         self._signer = signer
         self._contract_id = contract_id
 
-    def do_buyback(self, actions):
-        msg = {
-            "actions": actions
-        }
+    def execute_registered_buyback(self, route_id, amount_in, min_amount_out):
         return self._signer.function_call(
             self._contract_id,
-            "do_buyback",
+            "execute_registered_buyback",
             {
-                "swap_msg": json.dumps(msg)
+                "route_id": route_id,
+                "amount_in": str(amount_in),
+                "min_amount_out": str(min_amount_out)
             }, gas=300000000000000
         )
 
-
@@ -20,6 +20,7 @@ class GlobalConfig:
             self._buyback_token_out_contract = "token.burrow.near"
             self._buyback_pool_one = "3"
             self._buyback_pool_two = "3474"
+            self._buyback_route_id = 0
             self._indexer_url = "https://indexer.ref.finance/list-top-pools"
             self._near_rpc = "https://rpc.mainnet.near.org"
         elif self._near_env == "testnet":
@@ -31,6 +32,7 @@ class GlobalConfig:
             self._buyback_token_out_contract = "token.1689937928.burrow.testnet"
             self._buyback_pool_one = "465"
             self._buyback_pool_two = "714"
+            self._buyback_route_id = 0
             self._indexer_url = "https://dev-indexer.ref-finance.com/list-pools"
             self._near_rpc = "https://rpc.testnet.near.org"
         else:
@@ -72,6 +74,10 @@ class GlobalConfig:
     def buyback_pool_two(self):
         return self._buyback_pool_two
 
+    @property
+    def buyback_route_id(self):
+        return self._buyback_route_id
+
     @property
     def indexer_url(self):
         return self._indexer_url
@@ -1,12 +1,40 @@
 use crate::*;
-use near_sdk::{promise_result_as_success, is_promise_success};
+use near_sdk::{promise_result_as_success, is_promise_success, Promise, PromiseResult};
 
 pub const GAS_FOR_FT_TRANSFER: Gas = Gas(Gas::ONE_TERA.0 * 10);
 pub const GAS_FOR_FT_TRANSFER_CALLBACK: Gas = Gas(Gas::ONE_TERA.0 * 5);
 pub const GAS_FOR_FT_TRANSFER_CALL: Gas = Gas(20 * Gas::ONE_TERA.0);
 pub const GAS_FOR_FT_BALANCE_OF: Gas = Gas(10 * Gas::ONE_TERA.0);
-pub const GAS_FOR_FT_TRANSFER_CALL_CALLBACK: Gas = Gas(20 * Gas::ONE_TERA.0);
+pub const GAS_FOR_BUYBACK_RECEIVED_CALLBACK: Gas = Gas(10 * Gas::ONE_TERA.0);
+pub const GAS_FOR_FT_TRANSFER_CALL_CALLBACK: Gas = Gas(20 * Gas::ONE_TERA.0 + GAS_FOR_FT_BALANCE_OF.0 + GAS_FOR_BUYBACK_RECEIVED_CALLBACK.0
+    + GAS_FOR_FT_TRANSFER.0 + GAS_FOR_PAY_BOUNTY_CALLBACK.0);
+pub const GAS_FOR_START_BUYBACK_CALLBACK: Gas = Gas(10 * Gas::ONE_TERA.0 + GAS_FOR_FT_TRANSFER_CALL.0 + GAS_FOR_FT_TRANSFER_CALL_CALLBACK.0);
 pub const GAS_FOR_TO_DISTRIBUTE_CALLBACK: Gas = Gas(20 * Gas::ONE_TERA.0);
+pub const GAS_FOR_GET_RETURN: Gas = Gas(10 * Gas::ONE_TERA.0);
+pub const GAS_FOR_QUOTE_CALLBACK: Gas = Gas(10 * Gas::ONE_TERA.0);
+
+/// Gas of callback_start_buyback, which sends one ft_transfer_call per route.
+pub fn start_buyback_gas(routes: usize) -> Gas {
+    Gas(GAS_FOR_START_BUYBACK_CALLBACK.0 + GAS_FOR_FT_TRANSFER_CALL.0 * (routes as u64).saturating_sub(1))
+}
+
+/// The route a swap falls in, with the swaps of all routes counted one after another, and its index in the route.
+pub(crate) fn locate_hop(routes: &[Vec<Action>], hop: usize) -> (usize, usize) {
+    let mut route_hop = hop;
+    for (route, actions) in routes.iter().enumerate() {
+        if route_hop < actions.len() {
+            return (route, route_hop);
+        }
+        route_hop -= actions.len();
+    }
+    env::panic_str(ERR100_WRONG_MSG_FORMAT)
+}
+
+/// The fund token a route spends.
+pub(crate) fn route_fund_token_id(actions: &[Action]) -> &AccountId {
+    let Action::Swap(first_swap) = &actions[0];
+    &first_swap.token_in
+}
 
 #[ext_contract(ext_fungible_token)]
 pub trait FungibleToken {
@@ -21,6 +49,12 @@ pub trait FungibleToken {
     fn ft_balance_of(&self, account_id: AccountId) -> U128;
 }
 
+#[ext_contract(ext_ref_exchange)]
+pub trait RefExchangeContract {
+    fn get_return(&self, pool_id: u64, token_in: AccountId, amount_in: U128, token_out: AccountId) -> U128;
+    fn get_pool(&self, pool_id: u64) -> RefPoolInfo;
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct SwapAction {
@@ -60,193 +94,379 @@ pub enum SwapMessage {
 
 #[near_bindgen]
 impl Contract {
-    pub fn do_buyback(&mut self, swap_msg: String) {
+    /// Swap along swap_msg, and along each of split_swap_msgs as well to spread a large amount_in over more pools.
+    /// Every route goes to the exchange in its own ft_transfer_call and must spend the same fund token.
+    /// Guardians can't call it while the owner limits them to registered routes.
+    pub fn do_buyback(&mut self, swap_msg: String, split_swap_msgs: Option<Vec<String>>) {
         self.assert_contract_running();
         self.assert_owner_or_guardians();
-        let swap_info = serde_json::from_str::<SwapMessage>(&swap_msg).expect(ERR100_WRONG_MSG_FORMAT);
-        let available_fund_amount = self.available_fund_amount();
-        let mut amount_in = 0;
-        match swap_info {
-            SwapMessage::Execute {
-                referral_id: _,
-                actions,
-            } => {
-                require!(!actions.is_empty(), "Invalid actions");
-                for (index, action) in actions.iter().enumerate() {
-                    if index == 0 {
-                        match action {
-                            Action::Swap(swap_action) => {
-                                require!(swap_action.token_in == self.data().current_round_fund_token_id, "Invalid token_in");
-                                amount_in = swap_action.amount_in.expect(ERR100_WRONG_MSG_FORMAT).0;
-                                require!(amount_in > 0 && amount_in <= available_fund_amount, "Invalid amount_in");
-                            } 
-                        }
-                    } else if index == actions.len() - 1 {
-                        match action {
-                            Action::Swap(swap_action) => {
-                                require!(swap_action.token_out == self.data().buyback_token_id, "Invalid token_out");
-                                require!(swap_action.amount_in.is_none(), "Invalid amount_in");
-                            } 
-                        }
-                    } else {
-                        match action {
-                            Action::Swap(swap_action) => {
-                                require!(swap_action.amount_in.is_none(), "Invalid amount_in");
-                            } 
-                        }
-                    }
-                }
-            }
-        }
-
-        ext_fungible_token::ext(self.data().current_round_fund_token_id.clone())
-            .with_attached_deposit(1)
-            .with_static_gas(GAS_FOR_FT_TRANSFER_CALL)
-            .ft_transfer_call(
-                self.data().ref_exchange_id.clone(), 
-                U128(amount_in), 
-                None, 
-                swap_msg
-            ).then(
-                Self::ext(env::current_account_id())
-                    .with_static_gas(GAS_FOR_FT_TRANSFER_CALL_CALLBACK)
-                    .callback_do_buyback()
-            );
+        require!(!self.data().registered_routes_only || env::predecessor_account_id() == self.data().owner_id, ERR151_REGISTERED_ROUTES_ONLY);
+        let mut swap_msgs = vec![swap_msg];
+        swap_msgs.extend(split_swap_msgs.unwrap_or_default());
+        self.internal_do_buyback(swap_msgs);
     }
 
     pub fn distribute(&mut self) {
         self.assert_contract_running();
         self.assert_owner_or_guardians();
+        require!(self.internal_recipient_weights_valid(), ERR143_INVALID_RECIPIENT_WEIGHTS);
+        self.internal_start_operation(PendingOperation::Distribute);
+        let to_distribute_callback_gas = GAS_FOR_TO_DISTRIBUTE_CALLBACK.0
+            + self.data().recipients.iter().map(|recipient| recipient.delivery_mode.gas().0).sum::<u64>();
         ext_fungible_token::ext(self.data().buyback_token_id.clone())
             .with_static_gas(GAS_FOR_FT_BALANCE_OF)
             .ft_balance_of(
                 env::current_account_id()
             ).then(
                 Self::ext(env::current_account_id())
-                    .with_static_gas(GAS_FOR_TO_DISTRIBUTE_CALLBACK)
+                    .with_static_gas(Gas(to_distribute_callback_gas))
                     .callback_to_distribute()
             );
     }
 
     #[private]
-    pub fn callback_do_buyback(&mut self) {
-        let cross_call_result = promise_result_as_success().expect(ERR102_CROSS_CONTRACT_FAILED);
-        let cost = serde_json::from_slice::<U128>(&cross_call_result).unwrap().0;
-        self.data_mut().current_round_fund_cost += cost;
+    pub fn callback_quote_buyback(&mut self, round_id: u64, amount_in: U128, spend_time: u32, swap_msgs: Vec<String>, hop: u32, quoted_amount_out: U128) {
+        let quote = promise_result_as_success()
+            .and_then(|cross_call_result| serde_json::from_slice::<U128>(&cross_call_result).ok());
+        let quote = match quote {
+            Some(quote) => quote.0,
+            None => {
+                log!("Buyback on round {} aborted, {}", round_id, ERR102_CROSS_CONTRACT_FAILED);
+                self.internal_abort_buyback(round_id, amount_in.0, spend_time);
+                return;
+            }
+        };
+        let routes: Vec<Vec<Action>> = swap_msgs.iter().map(|swap_msg| swap_actions(swap_msg)).collect();
+        let (route, route_hop) = locate_hop(&routes, hop as usize);
+        let actions = &routes[route];
+        let next_hop = hop as usize + 1;
+        if route_hop + 1 < actions.len() {
+            self.internal_quote_hop(round_id, amount_in.0, spend_time, swap_msgs, next_hop, quote, quoted_amount_out.0);
+            return;
+        }
+        let Action::Swap(last_swap) = &actions[route_hop];
+        let min_amount_out_floor = ratio(quote, MAX_RATIO - self.data().max_slippage_bps);
+        if last_swap.min_amount_out.0 < min_amount_out_floor {
+            log!("Buyback on round {} aborted, {}, {} < {}", round_id, ERR129_MIN_AMOUNT_OUT_TOO_LOW, last_swap.min_amount_out.0, min_amount_out_floor);
+            self.internal_abort_buyback(round_id, amount_in.0, spend_time);
+            return;
+        }
+        let quoted_amount_out = quoted_amount_out.0 + quote;
+        // the next route starts from its own amount_in
+        if route + 1 < routes.len() {
+            let Action::Swap(first_swap) = &routes[route + 1][0];
+            let route_amount_in = first_swap.amount_in.expect(ERR100_WRONG_MSG_FORMAT).0;
+            self.internal_quote_hop(round_id, amount_in.0, spend_time, swap_msgs, next_hop, route_amount_in, quoted_amount_out);
+            return;
+        }
+
+        let mut round = self.internal_get_round(round_id);
+        round.last_quote_price = if quoted_amount_out > 0 { Some(u128_ratio(amount_in.0, PRICE_PRECISION, quoted_amount_out)) } else { None };
+        round.last_quote_time = Some(nano_to_sec(env::block_timestamp()));
+        self.internal_set_round(round_id, &round);
+        // the swap would fail on min_amount_out anyway, the fund keeps accumulating until the price comes down
+        if round.exceeds_max_price(amount_in.0, quoted_amount_out) {
+            log!("Buyback on round {} aborted, {}", round_id, ERR133_PRICE_ABOVE_MAX_PRICE);
+            self.internal_abort_buyback(round_id, amount_in.0, spend_time);
+            return;
+        }
+        // the received amount is the buyback token balance increase across the swaps
+        ext_fungible_token::ext(self.data().buyback_token_id.clone())
+            .with_static_gas(GAS_FOR_FT_BALANCE_OF)
+            .ft_balance_of(
+                env::current_account_id()
+            ).then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(start_buyback_gas(swap_msgs.len()))
+                    .callback_start_buyback(round_id, amount_in, spend_time, swap_msgs)
+            );
     }
 
     #[private]
-    pub fn callback_to_distribute(&mut self) {
-        let contract_data = self.data_mut();
-        assert!(contract_data.burn_rate + contract_data.company_rate + contract_data.reward_rate == MAX_RATIO);
-
-        let cross_call_result = promise_result_as_success().expect(ERR102_CROSS_CONTRACT_FAILED);
-        let new_distrbute_amount = serde_json::from_slice::<U128>(&cross_call_result).expect(ERR102_CROSS_CONTRACT_FAILED).0
-            - contract_data.belong_burn_amount
-            - contract_data.belong_company_amount
-            - contract_data.belong_reward_amount;
-
-        let new_distrbute_burn_amount = ratio(new_distrbute_amount, contract_data.burn_rate);
-        let new_distrbute_company_amount = ratio(new_distrbute_amount, contract_data.company_rate);
-        let new_distrbute_reward_amount = new_distrbute_amount - new_distrbute_burn_amount - new_distrbute_company_amount;
-
-        let burn_amount = new_distrbute_burn_amount + contract_data.belong_burn_amount;
-        let company_amount = new_distrbute_company_amount + contract_data.belong_company_amount;
-        let reward_amount = new_distrbute_reward_amount + contract_data.belong_reward_amount;
-        
-        contract_data.belong_burn_amount = 0;
-        contract_data.belong_company_amount = 0;
-        contract_data.belong_reward_amount = 0;
-
-        if burn_amount > 0 {
-            ext_fungible_token::ext(contract_data.buyback_token_id.clone())
+    pub fn callback_start_buyback(&mut self, round_id: u64, amount_in: U128, spend_time: u32, swap_msgs: Vec<String>) {
+        let balance_before = promise_result_as_success()
+            .and_then(|cross_call_result| serde_json::from_slice::<U128>(&cross_call_result).ok());
+        let balance_before = match balance_before {
+            Some(balance_before) => balance_before,
+            None => {
+                log!("Buyback on round {} aborted, {}", round_id, ERR102_CROSS_CONTRACT_FAILED);
+                self.internal_abort_buyback(round_id, amount_in.0, spend_time);
+                return;
+            }
+        };
+        let round = self.internal_get_round(round_id);
+        let mut transfers: Option<Promise> = None;
+        for swap_msg in swap_msgs.iter().cloned() {
+            let Action::Swap(first_swap) = &swap_actions(&swap_msg)[0];
+            let transfer = ext_fungible_token::ext(round.fund_token_id.clone())
                 .with_attached_deposit(1)
-                .with_static_gas(GAS_FOR_FT_TRANSFER)
-                .ft_transfer(
-                    contract_data.burn_account_id.clone(), 
-                    U128(burn_amount), 
-                    None
-                ).then(
-                    Self::ext(env::current_account_id())
-                        .with_static_gas(GAS_FOR_FT_TRANSFER_CALLBACK)
-                        .callback_ft_transfer(contract_data.burn_account_id.clone(), U128(burn_amount))
+                .with_static_gas(GAS_FOR_FT_TRANSFER_CALL)
+                .ft_transfer_call(
+                    self.data().ref_exchange_id.clone(), 
+                    first_swap.amount_in.expect(ERR100_WRONG_MSG_FORMAT), 
+                    None, 
+                    swap_msg
                 );
+            transfers = Some(match transfers {
+                Some(transfers) => transfers.and(transfer),
+                None => transfer,
+            });
         }
+        transfers.expect(ERR121_EMPTY_ROUTE).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_FT_TRANSFER_CALL_CALLBACK)
+                .callback_do_buyback(round_id, amount_in, spend_time, balance_before, swap_msgs)
+        );
+    }
 
-        if company_amount > 0 {
-            ext_fungible_token::ext(contract_data.buyback_token_id.clone())
-                .with_attached_deposit(1)
-                .with_static_gas(GAS_FOR_FT_TRANSFER)
-                .ft_transfer(
-                    contract_data.company_account_id.clone(), 
-                    U128(company_amount), 
-                    None
-                ).then(
-                    Self::ext(env::current_account_id())
-                        .with_static_gas(GAS_FOR_FT_TRANSFER_CALLBACK)
-                        .callback_ft_transfer(contract_data.company_account_id.clone(), U128(company_amount))
-                );
+    #[private]
+    pub fn callback_do_buyback(&mut self, round_id: u64, amount_in: U128, spend_time: u32, balance_before: U128, swap_msgs: Vec<String>) {
+        // ft_transfer_call returns the used amount, a failed transfer uses nothing.
+        // the fund of a route the token answers for unreadably may have left, so it is counted as used rather than refunded
+        let cost: u128 = swap_msgs.iter().enumerate()
+            .map(|(index, swap_msg)| match env::promise_result(index as u64) {
+                PromiseResult::Successful(cross_call_result) => match serde_json::from_slice::<U128>(&cross_call_result) {
+                    Ok(used_amount) => used_amount.0,
+                    Err(_) => {
+                        let Action::Swap(first_swap) = &swap_actions(swap_msg)[0];
+                        let route_amount_in = first_swap.amount_in.map(|amount| amount.0).unwrap_or(0);
+                        log!("Buyback on round {} can't read the used amount of route {}, {}, {} counted as used", round_id, index, ERR102_CROSS_CONTRACT_FAILED, route_amount_in);
+                        route_amount_in
+                    }
+                },
+                _ => 0,
+            })
+            .sum();
+        // never more than locked, so the unlock below can't underflow
+        let cost = cost.min(amount_in.0);
+        let mut round = self.internal_get_round(round_id);
+        round.fund_locked -= amount_in.0;
+        round.fund_cost += cost;
+        if cost > 0 {
+            round.executions += 1;
         }
-        
-        if reward_amount > 0 {
-            ext_fungible_token::ext(contract_data.buyback_token_id.clone())
-            .with_attached_deposit(1)
-            .with_static_gas(GAS_FOR_FT_TRANSFER)
-            .ft_transfer(
-                contract_data.reward_account_id.clone(), 
-                U128(reward_amount), 
-                None
+        self.internal_set_round(round_id, &round);
+        self.data_mut().pending_buyback = None;
+        self.internal_refund_spend(&round.fund_token_id, spend_time, amount_in.0 - cost);
+        self.internal_try_finish_active_round(&round.fund_token_id);
+
+        if cost == 0 {
+            self.internal_release_pending_bounty();
+            self.internal_finish_operation();
+            return;
+        }
+        let execution_id = self.data().executions.len();
+        let oracle_price = self.internal_current_oracle_price(&round.fund_token_id);
+        let pending_bounty = self.data().pending_bounty.clone();
+        self.data_mut().executions.push(&Execution {
+            round_id,
+            fund_token_id: round.fund_token_id.clone(),
+            amount_in: amount_in.0,
+            cost,
+            received_amount: 0,
+            time: nano_to_sec(env::block_timestamp()),
+            oracle_price,
+            keeper_id: pending_bounty.as_ref().map(|pending_bounty| pending_bounty.keeper_id.clone()),
+            bounty: pending_bounty.map_or(0, |pending_bounty| pending_bounty.amount),
+        });
+        self.internal_reward_keeper(execution_id, &round.fund_token_id, cost);
+        ext_fungible_token::ext(self.data().buyback_token_id.clone())
+            .with_static_gas(GAS_FOR_FT_BALANCE_OF)
+            .ft_balance_of(
+                env::current_account_id()
             ).then(
                 Self::ext(env::current_account_id())
-                    .with_static_gas(GAS_FOR_FT_TRANSFER_CALLBACK)
-                    .callback_ft_transfer(contract_data.reward_account_id.clone(), U128(reward_amount))
+                    .with_static_gas(GAS_FOR_BUYBACK_RECEIVED_CALLBACK)
+                    .callback_buyback_received(execution_id, balance_before)
             );
-        }
     }
 
     #[private]
-    pub fn callback_ft_transfer(&mut self, account_id: AccountId, amount: U128) {
-        if !is_promise_success() {
-            if account_id == self.data().burn_account_id {
-                self.data_mut().belong_burn_amount = amount.0;
+    pub fn callback_buyback_received(&mut self, execution_id: u64, balance_before: U128) {
+        self.internal_finish_operation();
+        let balance_after = promise_result_as_success()
+            .and_then(|cross_call_result| serde_json::from_slice::<U128>(&cross_call_result).ok());
+        let balance_after = match balance_after {
+            Some(balance_after) => balance_after,
+            None => {
+                log!("Failed to measure the buyback token received by execution {}", execution_id);
+                return;
             }
-            if account_id == self.data().company_account_id {
-                self.data_mut().belong_company_amount = amount.0;
+        };
+        let mut execution = self.internal_get_execution(execution_id);
+        execution.received_amount = balance_after.0.saturating_sub(balance_before.0);
+        self.data_mut().executions.replace(execution_id, &execution);
+        let mut round = self.internal_get_round(execution.round_id);
+        round.received_amount += execution.received_amount;
+        self.internal_set_round(execution.round_id, &round);
+        events::emit::buyback(execution_id, &execution);
+    }
+
+    #[private]
+    pub fn callback_to_distribute(&mut self) {
+        // nothing below may panic, which would roll the lock release back with it
+        let balance = promise_result_as_success()
+            .and_then(|cross_call_result| serde_json::from_slice::<U128>(&cross_call_result).ok());
+        self.internal_finish_operation();
+        let balance = match balance {
+            Some(balance) => balance.0,
+            None => {
+                log!("Distribute aborted, {}", ERR102_CROSS_CONTRACT_FAILED);
+                return;
             }
-            if account_id == self.data().reward_account_id {
-                self.data_mut().belong_reward_amount = amount.0;
+        };
+        if !self.internal_recipient_weights_valid() {
+            log!("Distribute aborted, {}", ERR143_INVALID_RECIPIENT_WEIGHTS);
+            return;
+        }
+
+        let reserved_amount = self.internal_reserved_amount();
+        if balance < reserved_amount {
+            log!("Nothing new to distribute, balance {} below the reserved {}", balance, reserved_amount);
+        }
+        let new_distrbute_amount = balance.saturating_sub(reserved_amount);
+
+        // the rounding dust of the shares stays in the contract for the next distribute
+        let mut recipients = std::mem::take(&mut self.data_mut().recipients);
+        for recipient in recipients.iter_mut() {
+            let amount = ratio(new_distrbute_amount, recipient.weight_bps) + recipient.owed_amount;
+            recipient.owed_amount = 0;
+            if amount > 0 {
+                self.internal_deliver_share(recipient, amount);
             }
         }
+        self.data_mut().recipients = recipients;
+    }
+
+    #[private]
+    pub fn callback_ft_transfer(&mut self, account_id: AccountId, amount: U128) {
+        self.internal_resolve_delivery(amount.0);
+        if !is_promise_success() {
+            self.internal_add_owed_amount(&account_id, amount.0);
+        }
     }
 }
 
 impl Contract {
-    pub fn available_fund_amount(&self) -> u128 {
-        let current_time = nano_to_sec(env::block_timestamp());
-        let contract_data = self.data();
-        if current_time <= contract_data.current_round_start_time + contract_data.total_buyback_time {
-            let pass_time = current_time.checked_sub(contract_data.current_round_start_time).unwrap_or(0);
-            let numerator = (pass_time / contract_data.buyback_internal) as u128;
-            let denominator = (contract_data.total_buyback_time / contract_data.buyback_internal) as u128;
-            contract_data.current_round_fund_amount * numerator / denominator - contract_data.current_round_fund_cost
+    /// Lock amount_in of the active round and start the swaps, with the caller already checked.
+    /// Each swap_msg is a route of its own, all spending the same fund token.
+    pub fn internal_do_buyback(&mut self, swap_msgs: Vec<String>) {
+        require!(!swap_msgs.is_empty() && swap_msgs.len() <= MAX_SPLIT_ROUTES, ERR141_TOO_MANY_SPLIT_ROUTES);
+        let routes: Vec<Vec<Action>> = swap_msgs.iter().map(|swap_msg| swap_actions(swap_msg)).collect();
+        require!(routes.iter().map(|actions| actions.len()).sum::<usize>() <= MAX_SPLIT_HOPS, ERR141_TOO_MANY_SPLIT_ROUTES);
+        let mut pool_ids = vec![];
+        // (amount_in, min_amount_out) of each route
+        let mut route_amounts = vec![];
+        for actions in routes.iter() {
+            if let Err(err) = validate_route(actions, &self.data().buyback_token_id) {
+                env::panic_str(&err.to_string());
+            }
+            for action in actions {
+                let Action::Swap(swap_action) = action;
+                require!(self.internal_is_pool_allowed(swap_action.pool_id), ERR139_POOL_NOT_WHITELISTED);
+                // split routes are swapped one after another, a pool shared by them would be quoted at the wrong reserves
+                require!(!pool_ids.contains(&swap_action.pool_id), ERR125_REPEATED_POOL);
+                pool_ids.push(swap_action.pool_id);
+            }
+            let Action::Swap(first_swap) = &actions[0];
+            let Action::Swap(last_swap) = &actions[actions.len() - 1];
+            require!(last_swap.min_amount_out.0 > 0, ERR127_ZERO_MIN_AMOUNT_OUT);
+            route_amounts.push((first_swap.amount_in.expect(ERR100_WRONG_MSG_FORMAT).0, last_swap.min_amount_out.0));
+        }
+        let fund_token_id = route_fund_token_id(&routes[0]).clone();
+        require!(routes.iter().all(|actions| *route_fund_token_id(actions) == fund_token_id), ERR142_SPLIT_FUND_TOKEN_MISMATCH);
+        if let Some((fund_price, buyback_price)) = self.internal_oracle_prices(&fund_token_id) {
+            for (amount_in, min_amount_out) in route_amounts.iter() {
+                let oracle_amount_out = oracle_amount_out(*amount_in, &fund_price, &buyback_price).expect(ERR131_STALE_ORACLE_PRICE);
+                let min_amount_out_floor = ratio(oracle_amount_out, MAX_RATIO - self.data().max_slippage_bps);
+                require!(*min_amount_out >= min_amount_out_floor, ERR132_MIN_AMOUNT_OUT_BELOW_ORACLE);
+            }
+        }
+
+        self.internal_roll_over_ended_rounds(&fund_token_id);
+        let round_id = self.internal_active_round_id(&fund_token_id).expect(ERR103_NO_ACTIVE_ROUND);
+        let mut round = self.internal_get_round(round_id);
+        let amount_in: u128 = route_amounts.iter().map(|(amount_in, _)| amount_in).sum();
+        let available_fund_amount = round.available_fund_amount(self.internal_schedule_time());
+        require!(route_amounts.iter().all(|(amount_in, _)| *amount_in > 0) && amount_in <= available_fund_amount, "Invalid amount_in");
+        for (amount_in, min_amount_out) in route_amounts.iter() {
+            require!(!round.exceeds_max_price(*amount_in, *min_amount_out), ERR133_PRICE_ABOVE_MAX_PRICE);
+        }
+
+        require!(!self.internal_transfers_in_flight(), ERR152_TRANSFERS_IN_FLIGHT);
+        let spend_time = nano_to_sec(env::block_timestamp());
+        self.internal_spend(&fund_token_id, amount_in, spend_time);
+        self.internal_start_operation(PendingOperation::Buyback);
+        round.fund_locked += amount_in;
+        self.internal_set_round(round_id, &round);
+        self.data_mut().pending_buyback = Some(PendingBuyback {
+            round_id,
+            amount_in,
+            spend_time,
+        });
+
+        // quote the routes hop by hop on the exchange to check min_amount_out before swapping,
+        // after checking the pools are deep enough if any token of the routes asks for it
+        if self.internal_needs_pool_check(&routes) {
+            self.internal_check_pools(round_id, amount_in, spend_time, swap_msgs, &routes);
         } else {
-            contract_data.current_round_fund_amount - contract_data.current_round_fund_cost
+            let route_amount_in = route_amounts[0].0;
+            self.internal_quote_hop(round_id, amount_in, spend_time, swap_msgs, 0, route_amount_in, 0);
         }
     }
+
+    /// Ask the exchange what the swap at `hop` returns for `hop_amount_in`, with the swaps of all routes counted one after another.
+    /// `quoted_amount_out` is what the routes before it have been quoted.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn internal_quote_hop(&self, round_id: u64, amount_in: u128, spend_time: u32, swap_msgs: Vec<String>, hop: usize, hop_amount_in: u128, quoted_amount_out: u128) {
+        let routes: Vec<Vec<Action>> = swap_msgs.iter().map(|swap_msg| swap_actions(swap_msg)).collect();
+        let (route, route_hop) = locate_hop(&routes, hop);
+        let Action::Swap(swap_action) = &routes[route][route_hop];
+        // every quote callback but the last one quotes the next hop, of this route or the next
+        let hops_left = (routes.iter().flatten().count() - hop - 1) as u64;
+        let quote_callback_gas = GAS_FOR_QUOTE_CALLBACK.0 * (hops_left + 1)
+            + GAS_FOR_GET_RETURN.0 * hops_left
+            + GAS_FOR_FT_BALANCE_OF.0
+            + start_buyback_gas(swap_msgs.len()).0;
+        ext_ref_exchange::ext(self.data().ref_exchange_id.clone())
+            .with_static_gas(GAS_FOR_GET_RETURN)
+            .get_return(
+                swap_action.pool_id,
+                swap_action.token_in.clone(),
+                U128(hop_amount_in),
+                swap_action.token_out.clone()
+            ).then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(Gas(quote_callback_gas))
+                    .callback_quote_buyback(round_id, U128(amount_in), spend_time, swap_msgs, hop as u32, U128(quoted_amount_out))
+            );
+    }
+
+    pub fn available_fund_amount(&self, fund_token_id: &AccountId) -> u128 {
+        self.internal_active_round_id(fund_token_id)
+            .map(|round_id| self.internal_get_round(round_id).available_fund_amount(self.internal_schedule_time()))
+            .unwrap_or(0)
+    }
 }
 
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
     use super::*;
     use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
     use near_sdk::json_types::U128;
     use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::{PromiseResult, RuntimeFeesConfig, VMConfig};
+    use std::collections::HashMap;
     pub use near_sdk::{testing_env, serde_json, AccountId, Balance};
 
     pub fn owner_id() -> AccountId {
         AccountId::new_unchecked("owner_id".to_string())
     }
-    
+
+    pub fn contract_account_id() -> AccountId {
+        AccountId::new_unchecked("buyback".to_string())
+    }
+
     pub fn burn_account_id() -> AccountId {
         AccountId::new_unchecked("burn".to_string())
     }
@@ -259,6 +479,15 @@ mod test {
         AccountId::new_unchecked("reward".to_string())
     }
 
+    pub fn recipient_configs(weights: Vec<(AccountId, u32)>) -> Vec<RecipientConfig> {
+        weights.into_iter().map(|(account_id, weight_bps)| RecipientConfig {
+            label: account_id.to_string(),
+            account_id,
+            weight_bps,
+            delivery_mode: DeliveryMode::Transfer,
+        }).collect()
+    }
+
     pub fn buyback_token_id() -> AccountId {
         AccountId::new_unchecked("buyback_token".to_string())
     }
@@ -267,6 +496,10 @@ mod test {
         AccountId::new_unchecked("nusdt".to_string())
     }
 
+    pub fn nusdc() -> AccountId {
+        AccountId::new_unchecked("nusdc".to_string())
+    }
+
     pub fn d(value: Balance, decimals: u8) -> Balance {
         value * 10u128.pow(decimals as _)
     }
@@ -275,27 +508,628 @@ mod test {
         u64::from(sec) * 10u64.pow(9)
     }
 
-    #[test]
-    fn base() {
+    pub fn buyback_info(start_time: u32, total_buyback_time: u32, buyback_internal: u32) -> String {
+        serde_json::to_string(&TokenReceiverMessage::BuyBackInfo {
+            current_round_start_time: start_time,
+            total_buyback_time,
+            buyback_internal,
+            release_curve: ReleaseCurve::Stepwise,
+            roll_over_remainder: false,
+            max_price: None,
+        }).unwrap()
+    }
+
+    pub fn swap_msg(token_in: AccountId, amount_in: Balance) -> String {
+        serde_json::to_string(&SwapMessage::Execute {
+            referral_id: None,
+            actions: vec![Action::Swap(SwapAction {
+                pool_id: 0,
+                token_in,
+                amount_in: Some(U128(amount_in)),
+                token_out: buyback_token_id(),
+                min_amount_out: U128(MIN_AMOUNT_OUT),
+            })]
+        }).unwrap()
+    }
+
+    /// A new contract with nusdt on the token white list, the context left at the owner attaching 1 yocto.
+    pub fn setup_contract() -> (VMContextBuilder, Contract) {
         let mut context = VMContextBuilder::new();
-        testing_env!(context.predecessor_account_id(owner_id()).build());
+        testing_env!(context.current_account_id(contract_account_id()).predecessor_account_id(owner_id()).build());
         let mut contract = Contract::new(owner_id(), burn_account_id(), company_account_id(), reward_account_id(), buyback_token_id());
         testing_env!(context.predecessor_account_id(owner_id()).attached_deposit(1).build());
         contract.extend_token_white_list(vec![nusdt()]);
+        (context, contract)
+    }
+
+    /// Deposit 100 nusdt with buyback_info at 1000, the context left at nusdt.
+    pub fn fund_round(contract: &mut Contract, context: &mut VMContextBuilder, buyback_info: String) {
+        testing_env!(context.block_timestamp(sec_to_nano(1000)).predecessor_account_id(nusdt()).build());
+        contract.ft_on_transfer(owner_id(), U128(d(100, 6)), buyback_info);
+    }
+
+    pub fn callback_with_result(context: &VMContextBuilder, result: PromiseResult) {
+        testing_env!(
+            context.build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![result]
+        );
+    }
+
+    pub fn pool_result(token_amounts: Vec<(AccountId, Balance)>) -> PromiseResult {
+        PromiseResult::Successful(serde_json::to_vec(&RefPoolInfo {
+            token_account_ids: token_amounts.iter().map(|(token_id, _)| token_id.clone()).collect(),
+            amounts: token_amounts.iter().map(|(_, amount)| U128(*amount)).collect(),
+        }).unwrap())
+    }
+
+    pub const BALANCE_BEFORE: Balance = 1000;
+    pub const MIN_AMOUNT_OUT: Balance = 10u128.pow(18);
+
+    /// Call do_buyback and let the quote and the balance check before the swap go through, returning the round it spends.
+    pub fn start_buyback(contract: &mut Contract, context: &mut VMContextBuilder, token_in: AccountId, amount_in: Balance) -> u64 {
+        testing_env!(context.predecessor_account_id(owner_id()).build());
+        contract.do_buyback(swap_msg(token_in.clone(), amount_in), None);
+        let round_id = contract.get_active_round(token_in.clone()).unwrap().round_id;
+        context.predecessor_account_id(contract_account_id());
+        let spend_time = nano_to_sec(context.context.block_timestamp);
+        callback_with_result(context, PromiseResult::Successful(serde_json::to_vec(&U128(MIN_AMOUNT_OUT)).unwrap()));
+        contract.callback_quote_buyback(round_id, U128(amount_in), spend_time, vec![swap_msg(token_in.clone(), amount_in)], 0, U128(0));
+        callback_with_result(context, PromiseResult::Successful(serde_json::to_vec(&U128(BALANCE_BEFORE)).unwrap()));
+        contract.callback_start_buyback(round_id, U128(amount_in), spend_time, vec![swap_msg(token_in, amount_in)]);
+        round_id
+    }
+
+    /// Resolve the swap with the exchange using `cost` (None if the swap failed) for `received_amount`.
+    pub fn finish_buyback(contract: &mut Contract, context: &mut VMContextBuilder, round_id: u64, amount_in: Balance, cost: Option<Balance>, received_amount: Balance) {
+        context.predecessor_account_id(contract_account_id());
+        let spend_time = nano_to_sec(context.context.block_timestamp);
+        callback_with_result(context, match cost {
+            Some(cost) => PromiseResult::Successful(serde_json::to_vec(&U128(cost)).unwrap()),
+            None => PromiseResult::Failed,
+        });
+        let fund_token_id = contract.internal_get_round(round_id).fund_token_id;
+        contract.callback_do_buyback(round_id, U128(amount_in), spend_time, U128(BALANCE_BEFORE), vec![swap_msg(fund_token_id, amount_in)]);
+        if cost.unwrap_or(0) > 0 {
+            callback_with_result(context, PromiseResult::Successful(serde_json::to_vec(&U128(BALANCE_BEFORE + received_amount)).unwrap()));
+            contract.callback_buyback_received(contract.data().executions.len() - 1, U128(BALANCE_BEFORE));
+        }
+    }
+
+    pub fn buyback(contract: &mut Contract, context: &mut VMContextBuilder, token_in: AccountId, amount_in: Balance, cost: Balance, received_amount: Balance) {
+        let round_id = start_buyback(contract, context, token_in, amount_in);
+        finish_buyback(contract, context, round_id, amount_in, Some(cost), received_amount);
+    }
+
+    #[test]
+    fn base() {
+        let (mut context, mut contract) = setup_contract();
         testing_env!(context.block_timestamp(sec_to_nano(1000)).predecessor_account_id(nusdt()).build());
         contract.ft_on_transfer(owner_id(), U128(d(100, 6)), serde_json::to_string(&TokenReceiverMessage::BuyBackInfo { 
             current_round_start_time: 1100, 
             total_buyback_time: 100, 
-            buyback_internal: 10 
+            buyback_internal: 10,
+            release_curve: ReleaseCurve::Stepwise,
+            roll_over_remainder: false,
+            max_price: None,
         }).unwrap());
         
 
-        assert_eq!(contract.get_available_fund_amount().0, 0); 
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, 0); 
         testing_env!(context.block_timestamp(sec_to_nano(1110)).build());
-        assert_eq!(contract.get_available_fund_amount().0, d(10, 6)); 
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(10, 6)); 
         testing_env!(context.block_timestamp(sec_to_nano(1150)).build());
-        assert_eq!(contract.get_available_fund_amount().0, d(50, 6)); 
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(50, 6)); 
+        testing_env!(context.block_timestamp(sec_to_nano(1200)).build());
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(100, 6)); 
+    }
+
+    #[test]
+    fn round_queue() {
+        let (mut context, mut contract) = setup_contract();
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+        contract.ft_on_transfer(owner_id(), U128(d(50, 6)), buyback_info(1100, 50, 10));
+        assert_eq!(contract.get_active_round(nusdt()).unwrap().round_id, 0);
+        assert_eq!(contract.get_pending_rounds(nusdt()).len(), 1);
+
+        // the second round has started, but the first one is consumed first
+        testing_env!(context.block_timestamp(sec_to_nano(1120)).predecessor_account_id(owner_id()).build());
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(100, 6));
+        let round_id = start_buyback(&mut contract, &mut context, nusdt(), d(100, 6));
+        assert_eq!(round_id, 0);
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, 0);
+        finish_buyback(&mut contract, &mut context, round_id, d(100, 6), Some(d(100, 6)), d(10, 18));
+        assert_eq!(contract.get_active_round(nusdt()).unwrap().round_id, 1);
+        assert!(contract.get_pending_rounds(nusdt()).is_empty());
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(20, 6));
+        let finished_rounds = contract.get_finished_rounds(None, None);
+        assert_eq!(finished_rounds.len(), 1);
+        assert_eq!(finished_rounds[0].fund_cost.0, d(100, 6));
+
+        // a failed swap unlocks the fund again
+        let round_id = start_buyback(&mut contract, &mut context, nusdt(), d(20, 6));
+        finish_buyback(&mut contract, &mut context, round_id, d(20, 6), None, 0);
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(20, 6));
+    }
+
+    #[test]
+    fn concurrent_rounds() {
+        let (mut context, mut contract) = setup_contract();
+        contract.extend_token_white_list(vec![nusdc()]);
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+        testing_env!(context.predecessor_account_id(nusdc()).build());
+        contract.ft_on_transfer(owner_id(), U128(d(200, 6)), buyback_info(1000, 100, 10));
+        assert_eq!(contract.get_active_rounds().len(), 2);
+
+        testing_env!(context.block_timestamp(sec_to_nano(1050)).predecessor_account_id(owner_id()).build());
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(50, 6));
+        assert_eq!(contract.get_available_fund_amount(nusdc()).0, d(100, 6));
+        start_buyback(&mut contract, &mut context, nusdc(), d(100, 6));
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(50, 6));
+        assert_eq!(contract.get_available_fund_amount(nusdc()).0, 0);
+    }
+
+    #[test]
+    fn cancel_round() {
+        let (mut context, mut contract) = setup_contract();
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+        contract.ft_on_transfer(owner_id(), U128(d(50, 6)), buyback_info(1100, 50, 10));
+
+        testing_env!(context.block_timestamp(sec_to_nano(1050)).predecessor_account_id(owner_id()).build());
+        buyback(&mut contract, &mut context, nusdt(), d(50, 6), d(50, 6), d(5, 18));
+
+        testing_env!(context.predecessor_account_id(owner_id()).attached_deposit(1).build());
+        contract.cancel_round(0, owner_id());
+        assert_eq!(contract.get_active_round(nusdt()).unwrap().round_id, 1);
+
+        // a failed refund puts the round back in front of the queue
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Failed);
+        contract.callback_cancel_round(0, owner_id(), U128(d(50, 6)), 0);
+        assert_eq!(contract.get_active_round(nusdt()).unwrap().round_id, 0);
+        assert_eq!(contract.get_pending_rounds(nusdt()).len(), 1);
+
+        testing_env!(context.predecessor_account_id(owner_id()).attached_deposit(1).build());
+        contract.cancel_round(0, owner_id());
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(vec![]));
+        contract.callback_cancel_round(0, owner_id(), U128(d(50, 6)), 0);
+        assert_eq!(contract.get_active_round(nusdt()).unwrap().round_id, 1);
+        let finished_rounds = contract.get_finished_rounds(None, None);
+        assert_eq!(finished_rounds.len(), 1);
+        assert_eq!(finished_rounds[0].status, RoundStatus::Cancelled);
+    }
+
+    #[test]
+    fn top_up_round() {
+        let (mut context, mut contract) = setup_contract();
+        contract.extend_token_white_list(vec![nusdc()]);
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+        testing_env!(context.predecessor_account_id(nusdc()).build());
+        contract.ft_on_transfer(owner_id(), U128(d(100, 6)), buyback_info(1000, 100, 10));
+
+        testing_env!(context.block_timestamp(sec_to_nano(1055)).predecessor_account_id(nusdt()).build());
+        let top_up = serde_json::to_string(&TokenReceiverMessage::TopUp { top_up_mode: TopUpMode::KeepEndTime }).unwrap();
+        contract.ft_on_transfer(owner_id(), U128(d(100, 6)), top_up);
+        testing_env!(context.predecessor_account_id(nusdc()).build());
+        let top_up = serde_json::to_string(&TokenReceiverMessage::TopUp { top_up_mode: TopUpMode::ExtendEndTime }).unwrap();
+        contract.ft_on_transfer(owner_id(), U128(d(100, 6)), top_up);
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(50, 6));
+        assert_eq!(contract.get_available_fund_amount(nusdc()).0, d(50, 6));
+        assert_eq!(contract.get_active_round(nusdt()).unwrap().end_time, 1100);
+        assert_eq!(contract.get_active_round(nusdc()).unwrap().end_time, 1200);
+
+        testing_env!(context.block_timestamp(sec_to_nano(1060)).build());
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(80, 6));
+        assert_eq!(contract.get_available_fund_amount(nusdc()).0, d(60, 6));
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).build());
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(200, 6));
+        assert_eq!(contract.get_available_fund_amount(nusdc()).0, d(100, 6));
         testing_env!(context.block_timestamp(sec_to_nano(1200)).build());
-        assert_eq!(contract.get_available_fund_amount().0, d(100, 6)); 
+        assert_eq!(contract.get_available_fund_amount(nusdc()).0, d(200, 6));
+    }
+
+    #[test]
+    fn clear_stuck_buyback() {
+        let (mut context, mut contract) = setup_contract();
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+        testing_env!(context.block_timestamp(sec_to_nano(1050)).build());
+        start_buyback(&mut contract, &mut context, nusdt(), d(30, 6));
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(20, 6));
+
+        // the exchange used 10 of the stuck buyback
+        testing_env!(context.predecessor_account_id(owner_id()).attached_deposit(1).build());
+        contract.clear_pending_operation(Some(U128(d(10, 6))));
+        let round = contract.get_round(0).unwrap();
+        assert_eq!((round.fund_locked.0, round.fund_cost.0), (0, d(10, 6)));
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(40, 6));
+        assert!(contract.get_metadata().pending_operation.is_none());
+
+        start_buyback(&mut contract, &mut context, nusdt(), d(40, 6));
+        testing_env!(context.predecessor_account_id(owner_id()).attached_deposit(1).build());
+        contract.clear_pending_operation(None);
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(40, 6));
+        assert_eq!(contract.get_round(0).unwrap().fund_cost.0, d(10, 6));
+    }
+
+    #[test]
+    fn pause_shifts_schedule() {
+        let (mut context, mut contract) = setup_contract();
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+        contract.ft_on_transfer(owner_id(), U128(d(100, 6)), buyback_info(1080, 100, 10));
+
+        testing_env!(context.block_timestamp(sec_to_nano(1035)).predecessor_account_id(owner_id()).build());
+        contract.change_state(RunningState::Paused);
+        assert_eq!(contract.get_metadata().paused_at, Some(1035));
+        // the release stands still while paused
+        testing_env!(context.block_timestamp(sec_to_nano(1135)).build());
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(30, 6));
+        contract.change_state(RunningState::Running);
+        assert_eq!(contract.get_metadata().paused_at, None);
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(30, 6));
+
+        let active_round = contract.get_active_round(nusdt()).unwrap();
+        assert_eq!(active_round.paused_duration, 100);
+        assert_eq!(active_round.end_time, 1200);
+        assert_eq!(contract.get_metadata().active_rounds[0].end_time, 1200);
+        // the round starting during the pause only shifts by the part after its start
+        let pending_round = &contract.get_pending_rounds(nusdt())[0];
+        assert_eq!(pending_round.paused_duration, 55);
+        assert_eq!(pending_round.end_time, 1235);
+
+        testing_env!(context.block_timestamp(sec_to_nano(1145)).build());
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(40, 6));
+        testing_env!(context.block_timestamp(sec_to_nano(1200)).build());
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(100, 6));
+    }
+
+    #[test]
+    fn roll_over_remainder() {
+        let (mut context, mut contract) = setup_contract();
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(owner_id()).build());
+        buyback(&mut contract, &mut context, nusdt(), d(60, 6), d(60, 6), d(6, 18));
+
+        // a round without the option leaves the remainder where it is
+        testing_env!(context.block_timestamp(sec_to_nano(1150)).predecessor_account_id(nusdt()).build());
+        contract.ft_on_transfer(owner_id(), U128(d(100, 6)), buyback_info(1150, 100, 10));
+        assert_eq!(contract.get_active_round(nusdt()).unwrap().round_id, 0);
+        testing_env!(context.predecessor_account_id(owner_id()).build());
+        contract.cancel_round(1, owner_id());
+
+        testing_env!(context.block_timestamp(sec_to_nano(1200)).predecessor_account_id(nusdt()).build());
+        contract.ft_on_transfer(owner_id(), U128(d(100, 6)), serde_json::to_string(&TokenReceiverMessage::BuyBackInfo {
+            current_round_start_time: 1200,
+            total_buyback_time: 100,
+            buyback_internal: 10,
+            release_curve: ReleaseCurve::Stepwise,
+            roll_over_remainder: true,
+            max_price: None,
+        }).unwrap());
+        let active_round = contract.get_active_round(nusdt()).unwrap();
+        assert_eq!(active_round.round_id, 2);
+        assert_eq!(active_round.fund_amount.0, d(140, 6));
+        assert_eq!(active_round.rolled_in_amount.0, d(40, 6));
+        assert_eq!(active_round.end_time, 1300);
+        let finished_rounds = contract.get_finished_rounds(None, None);
+        assert_eq!(finished_rounds[0].status, RoundStatus::Finished);
+        assert_eq!(finished_rounds[0].rolled_over_amount.0, d(40, 6));
+
+        testing_env!(context.block_timestamp(sec_to_nano(1250)).build());
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(70, 6));
+    }
+
+    #[test]
+    fn round_history() {
+        let (mut context, mut contract) = setup_contract();
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+        contract.ft_on_transfer(owner_id(), U128(d(50, 6)), buyback_info(1100, 50, 10));
+
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(owner_id()).build());
+        buyback(&mut contract, &mut context, nusdt(), d(60, 6), d(60, 6), d(6, 18));
+        buyback(&mut contract, &mut context, nusdt(), d(40, 6), d(40, 6), d(2, 18));
+        testing_env!(context.block_timestamp(sec_to_nano(1120)).predecessor_account_id(owner_id()).attached_deposit(1).build());
+        contract.cancel_round(1, owner_id());
+
+        let rounds = contract.list_rounds(None, None);
+        assert_eq!(rounds.len(), 2);
+        assert_eq!(rounds[0].fund_cost.0, d(100, 6));
+        assert_eq!(rounds[0].executions, 2);
+        assert_eq!(rounds[0].received_amount.0, d(8, 18));
+        // 100 USDT for 8 buyback token
+        assert_eq!(rounds[0].average_price, Some(U128(d(125, 5) * PRICE_PRECISION / d(1, 18))));
+        assert_eq!(rounds[0].closed_at, Some(1100));
+        assert!(!rounds[0].cancelled);
+        assert_eq!(rounds[1].closed_at, Some(1120));
+        assert!(rounds[1].cancelled);
+        assert_eq!(contract.list_rounds(Some(1), Some(10)).len(), 1);
+        assert_eq!(contract.get_round(1).unwrap().fund_amount.0, d(50, 6));
+        assert!(contract.get_round(2).is_none());
+    }
+
+    #[test]
+    fn buyback_received() {
+        let (mut context, mut contract) = setup_contract();
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(owner_id()).build());
+        let round_id = start_buyback(&mut contract, &mut context, nusdt(), d(40, 6));
+        assert_eq!(contract.get_metadata().pending_operation, Some(PendingOperation::Buyback));
+        finish_buyback(&mut contract, &mut context, round_id, d(40, 6), Some(d(40, 6)), d(3, 18));
+        assert_eq!(contract.get_metadata().pending_operation, None);
+
+        // a failed balance check before the swap gives the fund back
+        testing_env!(context.predecessor_account_id(owner_id()).build());
+        contract.do_buyback(swap_msg(nusdt(), d(10, 6)), None);
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(50, 6));
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Failed);
+        contract.callback_start_buyback(0, U128(d(10, 6)), 1100, vec![swap_msg(nusdt(), d(10, 6))]);
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(60, 6));
+        assert_eq!(contract.get_metadata().pending_operation, None);
+
+        let executions = contract.list_executions(None, None);
+        assert_eq!(executions.len(), 1);
+        assert_eq!(executions[0].round_id, 0);
+        assert_eq!(executions[0].cost.0, d(40, 6));
+        assert_eq!(executions[0].received_amount.0, d(3, 18));
+        assert_eq!(contract.get_execution(0).unwrap().time, 1100);
+        assert_eq!(contract.get_round(0).unwrap().received_amount.0, d(3, 18));
+    }
+
+    #[test]
+    #[should_panic(expected = "E119")]
+    fn one_operation_at_a_time() {
+        let (mut context, mut contract) = setup_contract();
+        contract.set_recipients(recipient_configs(vec![(burn_account_id(), 2000), (company_account_id(), 2000), (reward_account_id(), 6000)]));
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(owner_id()).build());
+        start_buyback(&mut contract, &mut context, nusdt(), d(40, 6));
+        testing_env!(context.predecessor_account_id(owner_id()).build());
+        contract.distribute();
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn min_amount_out_below_quote() {
+        let (mut context, mut contract) = setup_contract();
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+
+        // the quote allows a min_amount_out of at least 99% of it
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(owner_id()).build());
+        contract.do_buyback(swap_msg(nusdt(), d(40, 6)), None);
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(60, 6));
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(MIN_AMOUNT_OUT * 100 / 98)).unwrap()));
+        contract.callback_quote_buyback(0, U128(d(40, 6)), 1100, vec![swap_msg(nusdt(), d(40, 6))], 0, U128(0));
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(100, 6));
+        assert_eq!(contract.get_metadata().pending_operation, None);
+
+        // with a looser limit the same quote goes on to the swap
+        testing_env!(context.predecessor_account_id(owner_id()).attached_deposit(1).build());
+        contract.set_max_slippage_bps(300);
+        testing_env!(context.block_timestamp(sec_to_nano(1200)).predecessor_account_id(owner_id()).build());
+        contract.do_buyback(swap_msg(nusdt(), d(40, 6)), None);
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(MIN_AMOUNT_OUT * 100 / 98)).unwrap()));
+        contract.callback_quote_buyback(0, U128(d(40, 6)), 1200, vec![swap_msg(nusdt(), d(40, 6))], 0, U128(0));
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(60, 6));
+        assert_eq!(contract.get_metadata().pending_operation, Some(PendingOperation::Buyback));
+    }
+
+    #[test]
+    #[should_panic(expected = "E127")]
+    fn zero_min_amount_out() {
+        let (mut context, mut contract) = setup_contract();
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(owner_id()).build());
+        contract.do_buyback(serde_json::to_string(&SwapMessage::Execute {
+            referral_id: None,
+            actions: vec![Action::Swap(SwapAction {
+                pool_id: 0,
+                token_in: nusdt(),
+                amount_in: Some(U128(d(10, 6))),
+                token_out: buyback_token_id(),
+                min_amount_out: U128(0),
+            })]
+        }).unwrap(), None);
+    }
+
+    pub fn oracle_id() -> AccountId {
+        AccountId::new_unchecked("oracle".to_string())
+    }
+
+    /// Let the oracle report `prices` as (token, multiplier, decimals) at the current block time.
+    pub fn refresh_prices(contract: &mut Contract, context: &mut VMContextBuilder, prices: Vec<(AccountId, u128, u8)>) {
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(context, PromiseResult::Successful(serde_json::to_vec(&PriceData {
+            timestamp: near_sdk::json_types::U64(context.context.block_timestamp),
+            recency_duration_sec: 90,
+            prices: prices.into_iter().map(|(token_id, multiplier, decimals)| AssetOptionalPrice {
+                asset_id: token_id.to_string(),
+                price: Some(Price { multiplier: U128(multiplier), decimals }),
+            }).collect(),
+        }).unwrap()));
+        contract.callback_refresh_oracle_prices();
+    }
+
+    #[test]
+    fn oracle_price_floor() {
+        let (mut context, mut contract) = setup_contract();
+        contract.set_price_oracle(Some(oracle_id()));
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+
+        // 1 nusdt is worth 1 buyback token, so min_amount_out of 1 buyback token for it passes
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).build());
+        refresh_prices(&mut contract, &mut context, vec![(nusdt(), 10000, 10), (buyback_token_id(), 10000, 22)]);
+        assert_eq!(contract.get_oracle_fund_price(nusdt()).unwrap().0, PRICE_PRECISION / 10u128.pow(12));
+        assert!(contract.get_oracle_price(nusdt()).unwrap().fresh);
+        let round_id = start_buyback(&mut contract, &mut context, nusdt(), d(1, 6));
+        finish_buyback(&mut contract, &mut context, round_id, d(1, 6), Some(d(1, 6)), d(1, 18));
+        assert_eq!(contract.get_execution(0).unwrap().oracle_price.unwrap().0, PRICE_PRECISION / 10u128.pow(12));
+
+        // the price goes stale without a refresh
+        testing_env!(context.block_timestamp(sec_to_nano(1100 + DEFAULT_MAX_PRICE_AGE + 1)).build());
+        assert!(!contract.get_oracle_price(nusdt()).unwrap().fresh);
+        assert!(contract.get_oracle_fund_price(nusdt()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "E132")]
+    fn min_amount_out_below_oracle() {
+        let (mut context, mut contract) = setup_contract();
+        contract.set_price_oracle(Some(oracle_id()));
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).build());
+        refresh_prices(&mut contract, &mut context, vec![(nusdt(), 10000, 10), (buyback_token_id(), 10000, 22)]);
+        // 2 nusdt are worth 2 buyback token, min_amount_out of 1 buyback token is too low
+        testing_env!(context.predecessor_account_id(owner_id()).build());
+        contract.do_buyback(swap_msg(nusdt(), d(2, 6)), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "E131")]
+    fn stale_oracle_price() {
+        let (mut context, mut contract) = setup_contract();
+        contract.set_price_oracle(Some(oracle_id()));
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+        refresh_prices(&mut contract, &mut context, vec![(nusdt(), 10000, 10), (buyback_token_id(), 10000, 22)]);
+        testing_env!(context.block_timestamp(sec_to_nano(1000 + DEFAULT_MAX_PRICE_AGE + 1)).predecessor_account_id(owner_id()).build());
+        contract.do_buyback(swap_msg(nusdt(), d(1, 6)), None);
+    }
+
+    pub fn buyback_info_with_max_price(start_time: u32, total_buyback_time: u32, buyback_internal: u32, max_price: Balance) -> String {
+        serde_json::to_string(&TokenReceiverMessage::BuyBackInfo {
+            current_round_start_time: start_time,
+            total_buyback_time,
+            buyback_internal,
+            release_curve: ReleaseCurve::Stepwise,
+            roll_over_remainder: false,
+            max_price: Some(U128(max_price)),
+        }).unwrap()
+    }
+
+    #[test]
+    fn max_price_ceiling() {
+        let (mut context, mut contract) = setup_contract();
+        // never pay more than 50 nusdt per buyback token
+        fund_round(&mut contract, &mut context, buyback_info_with_max_price(1000, 100, 10, d(50, 6)));
+        assert_eq!(contract.get_active_round(nusdt()).unwrap().max_price.unwrap().0, d(50, 6));
+        assert!(!contract.get_price_ceiling_status(nusdt()).unwrap().blocking);
+
+        // the quote prices the buyback token at 80 nusdt, so the buyback is aborted
+        testing_env!(context.block_timestamp(sec_to_nano(1050)).predecessor_account_id(owner_id()).build());
+        contract.do_buyback(swap_msg(nusdt(), d(40, 6)), None);
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(MIN_AMOUNT_OUT / 2)).unwrap()));
+        contract.callback_quote_buyback(0, U128(d(40, 6)), 1050, vec![swap_msg(nusdt(), d(40, 6))], 0, U128(0));
+        assert_eq!(contract.get_metadata().pending_operation, None);
+        let status = contract.get_price_ceiling_status(nusdt()).unwrap();
+        assert!(status.blocking);
+        assert_eq!(status.last_quote_price.unwrap().0, d(80, 6));
+
+        // the fund keeps accumulating meanwhile, and goes out once the price comes down
+        testing_env!(context.block_timestamp(sec_to_nano(1080)).build());
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(80, 6));
+        let round_id = start_buyback(&mut contract, &mut context, nusdt(), d(40, 6));
+        finish_buyback(&mut contract, &mut context, round_id, d(40, 6), Some(d(40, 6)), d(1, 18));
+        assert!(!contract.get_price_ceiling_status(nusdt()).unwrap().blocking);
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(40, 6));
+    }
+
+    #[test]
+    #[should_panic(expected = "E133")]
+    fn min_amount_out_above_max_price() {
+        let (mut context, mut contract) = setup_contract();
+        fund_round(&mut contract, &mut context, buyback_info_with_max_price(1000, 100, 10, d(50, 6)));
+        // 60 nusdt for at least 1 buyback token
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(owner_id()).build());
+        contract.do_buyback(swap_msg(nusdt(), d(60, 6)), None);
+    }
+
+    pub fn guardian_id() -> AccountId {
+        AccountId::new_unchecked("guardian".to_string())
+    }
+
+    #[test]
+    fn split_buyback() {
+        let (mut context, mut contract) = setup_contract();
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+
+        // 30 nusdt straight into the buyback token, 20 through nusdc
+        let direct_route = RouteTemplate { fund_token_id: nusdt(), hops: vec![RouteHop { pool_id: 0, token_out: buyback_token_id() }] };
+        let stable_route = RouteTemplate { fund_token_id: nusdt(), hops: vec![
+            RouteHop { pool_id: 1, token_out: nusdc() },
+            RouteHop { pool_id: 2, token_out: buyback_token_id() },
+        ] };
+        let swap_msgs = vec![direct_route.to_swap_msg(d(30, 6), d(3, 18)), stable_route.to_swap_msg(d(20, 6), d(2, 18))];
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(owner_id()).build());
+        contract.do_buyback(swap_msgs[0].clone(), Some(vec![swap_msgs[1].clone()]));
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(50, 6));
+
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(3, 18))).unwrap()));
+        contract.callback_quote_buyback(0, U128(d(50, 6)), 1100, swap_msgs.clone(), 0, U128(0));
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(20, 6))).unwrap()));
+        contract.callback_quote_buyback(0, U128(d(50, 6)), 1100, swap_msgs.clone(), 1, U128(d(3, 18)));
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(2, 18))).unwrap()));
+        contract.callback_quote_buyback(0, U128(d(50, 6)), 1100, swap_msgs.clone(), 2, U128(d(3, 18)));
+        // quoted 5 buyback tokens for 50 nusdt over both routes
+        assert_eq!(contract.get_active_round(nusdt()).unwrap().last_quote_price.unwrap().0, d(10, 6));
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(BALANCE_BEFORE)).unwrap()));
+        contract.callback_start_buyback(0, U128(d(50, 6)), 1100, swap_msgs.clone());
+
+        // the stable route failed, only the direct one is spent
+        testing_env!(context.build(), VMConfig::test(), RuntimeFeesConfig::test(), HashMap::default(), vec![
+            PromiseResult::Successful(serde_json::to_vec(&U128(d(30, 6))).unwrap()),
+            PromiseResult::Failed,
+        ]);
+        contract.callback_do_buyback(0, U128(d(50, 6)), 1100, U128(BALANCE_BEFORE), swap_msgs);
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(BALANCE_BEFORE + d(3, 18))).unwrap()));
+        contract.callback_buyback_received(0, U128(BALANCE_BEFORE));
+        let execution = contract.get_execution(0).unwrap();
+        assert_eq!((execution.amount_in.0, execution.cost.0, execution.received_amount.0), (d(50, 6), d(30, 6), d(3, 18)));
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(70, 6));
+        assert_eq!(contract.get_spend_headroom(nusdt()).daily_amount_spent.0, d(30, 6));
+    }
+
+    #[test]
+    fn malformed_exchange_result() {
+        let (mut context, mut contract) = setup_contract();
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+
+        // an unreadable quote aborts the buyback and unlocks the fund
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(owner_id()).build());
+        contract.do_buyback(swap_msg(nusdt(), d(10, 6)), None);
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(b"nan".to_vec()));
+        contract.callback_quote_buyback(0, U128(d(10, 6)), 1100, vec![swap_msg(nusdt(), d(10, 6))], 0, U128(0));
+        assert_eq!(contract.get_metadata().pending_operation, None);
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(100, 6));
+
+        // an unreadable used amount of the swap releases the lock, the fund it may have spent is counted as used
+        let round_id = start_buyback(&mut contract, &mut context, nusdt(), d(10, 6));
+        callback_with_result(&context, PromiseResult::Successful(b"nan".to_vec()));
+        contract.callback_do_buyback(round_id, U128(d(10, 6)), 1100, U128(BALANCE_BEFORE), vec![swap_msg(nusdt(), d(10, 6))]);
+        assert_eq!(contract.get_metadata().pending_operation, Some(PendingOperation::Buyback));
+        callback_with_result(&context, PromiseResult::Successful(b"nan".to_vec()));
+        contract.callback_buyback_received(0, U128(BALANCE_BEFORE));
+        assert_eq!(contract.get_metadata().pending_operation, None);
+        let round = contract.get_active_round(nusdt()).unwrap();
+        assert_eq!((round.fund_locked.0, round.fund_cost.0), (0, d(10, 6)));
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(90, 6));
+    }
+
+    #[test]
+    #[should_panic(expected = "E142")]
+    fn split_buyback_fund_token_mismatch() {
+        let (mut context, mut contract) = setup_contract();
+        contract.extend_token_white_list(vec![nusdc()]);
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(owner_id()).build());
+        let usdc_route = RouteTemplate { fund_token_id: nusdc(), hops: vec![RouteHop { pool_id: 1, token_out: buyback_token_id() }] };
+        contract.do_buyback(swap_msg(nusdt(), d(10, 6)), Some(vec![usdc_route.to_swap_msg(d(10, 6), MIN_AMOUNT_OUT)]));
+    }
+}
@@ -0,0 +1,245 @@
+use crate::*;
+
+/// 1.0 in the fixed point math of the exponential curves.
+const CURVE_ONE: u128 = 10u128.pow(36);
+/// Powers below this are treated as zero, which keeps the computed curves monotonic
+/// despite the rounding error of the fixed point pow.
+const CURVE_POW_FLOOR: u128 = 10u128.pow(12);
+
+/// How the fund of a round is released over its schedule.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum ReleaseCurve {
+    /// Release in proportion to the elapsed time.
+    Linear,
+    /// Release an equal part at the end of every buyback_internal.
+    #[default]
+    Stepwise,
+    /// Release at the end of every buyback_internal, each part being
+    /// decay_bps of the previous one, so most of the fund goes out early.
+    ExponentialDecay { decay_bps: u32 },
+    /// The reverse of ExponentialDecay, most of the fund goes out late.
+    BackLoaded { decay_bps: u32 },
+    /// (time, cumulative released) points in bps of total_buyback_time and of the fund,
+    /// linearly interpolated from (0, 0).
+    Piecewise { points: Vec<(u32, u32)> },
+}
+
+impl ReleaseCurve {
+    pub fn validate(&self) -> Result<(), ScheduleError> {
+        match self {
+            ReleaseCurve::Linear | ReleaseCurve::Stepwise => Ok(()),
+            ReleaseCurve::ExponentialDecay { decay_bps } | ReleaseCurve::BackLoaded { decay_bps } => {
+                if *decay_bps > 0 && *decay_bps < MAX_RATIO {
+                    Ok(())
+                } else {
+                    Err(ScheduleError::InvalidReleaseCurve)
+                }
+            }
+            ReleaseCurve::Piecewise { points } => {
+                let mut prev = (0, 0);
+                for (index, point) in points.iter().enumerate() {
+                    if (index > 0 && point.0 <= prev.0) || point.1 < prev.1 {
+                        return Err(ScheduleError::InvalidReleaseCurve);
+                    }
+                    prev = *point;
+                }
+                if points.is_empty() || prev.0 > MAX_RATIO || prev.1 != MAX_RATIO {
+                    return Err(ScheduleError::InvalidReleaseCurve);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether the curve only moves at the end of every buyback_internal.
+    pub fn is_stepwise(&self) -> bool {
+        matches!(self, ReleaseCurve::Stepwise | ReleaseCurve::ExponentialDecay { .. } | ReleaseCurve::BackLoaded { .. })
+    }
+
+    /// The part of `amount` released after `pass_time` of a `total_time` long schedule.
+    pub fn released_amount(&self, amount: u128, pass_time: u32, total_time: u32, interval: u32) -> u128 {
+        if pass_time >= total_time {
+            return amount;
+        }
+        let total_steps = total_time / std::cmp::max(interval, 1);
+        let pass_steps = pass_time / std::cmp::max(interval, 1);
+        if self.is_stepwise() && total_steps == 0 {
+            return 0;
+        }
+        match self {
+            ReleaseCurve::Linear => u128_ratio(amount, pass_time as u128, total_time as u128),
+            ReleaseCurve::Stepwise => u128_ratio(amount, pass_steps as u128, total_steps as u128),
+            ReleaseCurve::ExponentialDecay { decay_bps } => {
+                let remain = curve_pow(*decay_bps, pass_steps);
+                let remain_at_end = curve_pow(*decay_bps, total_steps);
+                (U256::from(amount) * (U256::from(CURVE_ONE) - remain) / (U256::from(CURVE_ONE) - remain_at_end)).as_u128()
+            }
+            ReleaseCurve::BackLoaded { decay_bps } => {
+                let remain = curve_pow(*decay_bps, total_steps - pass_steps);
+                let remain_at_end = curve_pow(*decay_bps, total_steps);
+                (U256::from(amount) * (remain - remain_at_end) / (U256::from(CURVE_ONE) - remain_at_end)).as_u128()
+            }
+            ReleaseCurve::Piecewise { points } => {
+                // compare times scaled by total_time * MAX_RATIO to stay in integers
+                let pass = U256::from(pass_time) * U256::from(MAX_RATIO);
+                let total = U256::from(total_time);
+                let mut prev = (0, 0);
+                for point in points {
+                    let point_time = U256::from(point.0) * total;
+                    if pass < point_time {
+                        let span = U256::from(point.0 - prev.0) * total;
+                        let released = U256::from(prev.1) * span
+                            + U256::from(point.1 - prev.1) * (pass - U256::from(prev.0) * total);
+                        return (U256::from(amount) * released / (span * U256::from(MAX_RATIO))).as_u128();
+                    }
+                    prev = *point;
+                }
+                amount
+            }
+        }
+    }
+}
+
+/// (decay_bps / MAX_RATIO) ^ exp in CURVE_ONE fixed point, rounded down to zero below CURVE_POW_FLOOR.
+fn curve_pow(decay_bps: u32, mut exp: u32) -> U256 {
+    let one = U256::from(CURVE_ONE);
+    let mut base = U256::from(decay_bps) * one / U256::from(MAX_RATIO);
+    let mut result = one;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = result * base / one;
+        }
+        base = base * base / one;
+        exp >>= 1;
+    }
+    if result < U256::from(CURVE_POW_FLOOR) {
+        U256::zero()
+    } else {
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn curves() -> Vec<ReleaseCurve> {
+        vec![
+            ReleaseCurve::Linear,
+            ReleaseCurve::Stepwise,
+            ReleaseCurve::ExponentialDecay { decay_bps: 1 },
+            ReleaseCurve::ExponentialDecay { decay_bps: 5000 },
+            ReleaseCurve::ExponentialDecay { decay_bps: 9999 },
+            ReleaseCurve::BackLoaded { decay_bps: 1 },
+            ReleaseCurve::BackLoaded { decay_bps: 5000 },
+            ReleaseCurve::BackLoaded { decay_bps: 9999 },
+            ReleaseCurve::Piecewise { points: vec![(MAX_RATIO, MAX_RATIO)] },
+            ReleaseCurve::Piecewise { points: vec![(0, 1000), (2500, 1000), (5000, 8000), (7000, MAX_RATIO)] },
+            ReleaseCurve::Piecewise { points: vec![(1, 1), (9999, 9999), (MAX_RATIO, MAX_RATIO)] },
+        ]
+    }
+
+    fn schedules() -> Vec<(u32, u32)> {
+        // (total_time, interval)
+        vec![(100, 10), (86400, 7200), (7 * 86400, 60), (3, 1), (u32::MAX - u32::MAX % 3600, 3600)]
+    }
+
+    fn amounts() -> Vec<u128> {
+        vec![1, 7, 10u128.pow(6), 123_456_789_123_456_789, 10u128.pow(36), u128::MAX / 10u128.pow(6)]
+    }
+
+    fn sample_times(total_time: u32, interval: u32) -> Vec<u32> {
+        let mut times: Vec<u32> = (0..=200u64).map(|i| (total_time as u64 * i / 200) as u32).collect();
+        for step in [0, 1, 2, total_time / interval / 2, total_time / interval - 1, total_time / interval] {
+            let time = step * interval;
+            times.extend([time.saturating_sub(1), time, time.saturating_add(1)]);
+        }
+        times.push(total_time.saturating_add(interval));
+        times.sort_unstable();
+        times.dedup();
+        times
+    }
+
+    #[test]
+    fn curves_are_valid() {
+        for curve in curves() {
+            assert_eq!(curve.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn piecewise_must_end_at_full_release() {
+        let curve = ReleaseCurve::Piecewise { points: vec![(5000, 5000), (MAX_RATIO, 9000)] };
+        assert_eq!(curve.validate(), Err(ScheduleError::InvalidReleaseCurve));
+        let curve = ReleaseCurve::Piecewise { points: vec![(5000, 5000), (MAX_RATIO + 1, MAX_RATIO)] };
+        assert_eq!(curve.validate(), Err(ScheduleError::InvalidReleaseCurve));
+        let curve = ReleaseCurve::Piecewise { points: vec![] };
+        assert_eq!(curve.validate(), Err(ScheduleError::InvalidReleaseCurve));
+    }
+
+    #[test]
+    fn piecewise_must_not_decrease() {
+        let curve = ReleaseCurve::Piecewise { points: vec![(5000, 6000), (6000, 5000), (MAX_RATIO, MAX_RATIO)] };
+        assert_eq!(curve.validate(), Err(ScheduleError::InvalidReleaseCurve));
+        let curve = ReleaseCurve::Piecewise { points: vec![(5000, 6000), (5000, 7000), (MAX_RATIO, MAX_RATIO)] };
+        assert_eq!(curve.validate(), Err(ScheduleError::InvalidReleaseCurve));
+    }
+
+    #[test]
+    fn decay_must_be_below_one() {
+        let curve = ReleaseCurve::ExponentialDecay { decay_bps: MAX_RATIO };
+        assert_eq!(curve.validate(), Err(ScheduleError::InvalidReleaseCurve));
+        let curve = ReleaseCurve::BackLoaded { decay_bps: 0 };
+        assert_eq!(curve.validate(), Err(ScheduleError::InvalidReleaseCurve));
+    }
+
+    #[test]
+    fn curves_never_decrease_and_end_at_full_release() {
+        for curve in curves() {
+            for (total_time, interval) in schedules() {
+                for amount in amounts() {
+                    let mut prev = 0;
+                    for pass_time in sample_times(total_time, interval) {
+                        let released = curve.released_amount(amount, pass_time, total_time, interval);
+                        assert!(released >= prev, "{:?} decreased at {}/{} for {}", curve, pass_time, total_time, amount);
+                        assert!(released <= amount, "{:?} exceeded the fund at {}/{} for {}", curve, pass_time, total_time, amount);
+                        prev = released;
+                    }
+                    assert_eq!(curve.released_amount(amount, total_time, total_time, interval), amount);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_step_of_exponential_curves() {
+        let (total_time, interval) = (7 * 86400, 60);
+        for decay_bps in [1, 9000, 9999] {
+            for curve in [ReleaseCurve::ExponentialDecay { decay_bps }, ReleaseCurve::BackLoaded { decay_bps }] {
+                let amount = 10u128.pow(36);
+                let mut prev = 0;
+                for step in 0..=total_time / interval {
+                    let released = curve.released_amount(amount, step * interval, total_time, interval);
+                    assert!(released >= prev);
+                    prev = released;
+                }
+                assert_eq!(prev, amount);
+            }
+        }
+    }
+
+    #[test]
+    fn curve_shapes() {
+        let amount = 10u128.pow(6);
+        assert_eq!(ReleaseCurve::Linear.released_amount(amount, 25, 100, 10), amount / 4);
+        assert_eq!(ReleaseCurve::Stepwise.released_amount(amount, 25, 100, 10), amount / 5);
+        // half of the fund is released in the first of two steps
+        assert_eq!(ReleaseCurve::ExponentialDecay { decay_bps: 5000 }.released_amount(amount, 10, 20, 10), amount * 2 / 3);
+        assert_eq!(ReleaseCurve::BackLoaded { decay_bps: 5000 }.released_amount(amount, 10, 20, 10), amount / 3);
+        let piecewise = ReleaseCurve::Piecewise { points: vec![(5000, 8000), (MAX_RATIO, MAX_RATIO)] };
+        assert_eq!(piecewise.released_amount(amount, 25, 100, 10), amount * 4 / 10);
+        assert_eq!(piecewise.released_amount(amount, 75, 100, 10), amount * 9 / 10);
+    }
+}
@@ -10,5 +10,56 @@ pub const ERR6_CONTRACT_PAUSED: &str = "E006: contract paused";
 
 // buyback
 pub const ERR100_WRONG_MSG_FORMAT: &str = "E100: illegal msg in ft_transfer_call";
-pub const ERR101_BUYBACK_IN_PROGRESS: &str = "E101: the current round of buyback has not concluded yet";
-pub const ERR102_CROSS_CONTRACT_FAILED: &str ="E102: cross contract call failed";
\ No newline at end of file
+pub const ERR102_CROSS_CONTRACT_FAILED: &str ="E102: cross contract call failed";
+pub const ERR103_NO_ACTIVE_ROUND: &str = "E103: no active buyback round";
+pub const ERR104_ROUND_NOT_FOUND: &str = "E104: buyback round not found";
+pub const ERR105_INVALID_FUND_AMOUNT: &str = "E105: invalid fund amount";
+pub const ERR106_ROUND_NOT_QUEUED: &str = "E106: buyback round is not queued";
+pub const ERR107_ROUND_FUND_LOCKED: &str = "E107: buyback round has fund locked in an unfinished buyback";
+pub const ERR108_ROUND_SCHEDULE_ENDED: &str = "E108: buyback round schedule has ended";
+pub const ERR109_SCHEDULE_OVERFLOW: &str = "E109: buyback schedule overflow";
+pub const ERR110_INVALID_RELEASE_CURVE: &str = "E110: invalid release curve";
+pub const ERR111_ZERO_BUYBACK_INTERNAL: &str = "E111: buyback_internal must be greater than 0";
+pub const ERR112_ZERO_BUYBACK_TIME: &str = "E112: total_buyback_time must be greater than 0";
+pub const ERR113_BUYBACK_TIME_NOT_MULTIPLE_OF_INTERNAL: &str = "E113: total_buyback_time must be a multiple of buyback_internal";
+pub const ERR114_START_TIME_TOO_EARLY: &str = "E114: round start time too far in the past";
+pub const ERR115_START_TIME_TOO_LATE: &str = "E115: round start time too far in the future";
+pub const ERR116_EXCEED_MAX_AMOUNT_IN: &str = "E116: amount_in exceeds the max amount_in per buyback";
+pub const ERR117_EXCEED_DAILY_SPEND: &str = "E117: amount_in exceeds the max spend in 24 hours";
+pub const ERR118_BUYBACK_TOO_SOON: &str = "E118: min gap between buybacks not reached";
+pub const ERR119_OPERATION_IN_PROGRESS: &str = "E119: another buyback or distribute is in progress";
+pub const ERR120_EXECUTION_NOT_FOUND: &str = "E120: buyback execution not found";
+pub const ERR121_EMPTY_ROUTE: &str = "E121: buyback route has no swap";
+pub const ERR122_TOO_MANY_HOPS: &str = "E122: buyback route has too many swaps";
+pub const ERR123_ROUTE_NOT_CONTINUOUS: &str = "E123: token_in differs from token_out of the previous swap";
+pub const ERR124_INVALID_ROUTE_TOKEN_OUT: &str = "E124: buyback route doesn't end in the buyback token";
+pub const ERR125_REPEATED_POOL: &str = "E125: buyback route uses a pool twice";
+pub const ERR126_INVALID_ROUTE_AMOUNT_IN: &str = "E126: only the first swap of a buyback route has amount_in";
+pub const ERR127_ZERO_MIN_AMOUNT_OUT: &str = "E127: min_amount_out of the last swap must be greater than 0";
+pub const ERR128_INVALID_MAX_SLIPPAGE: &str = "E128: max slippage must be less than 10000 bps";
+pub const ERR129_MIN_AMOUNT_OUT_TOO_LOW: &str = "E129: min_amount_out below the exchange quote minus max slippage";
+pub const ERR130_NO_PRICE_ORACLE: &str = "E130: price oracle not set";
+pub const ERR131_STALE_ORACLE_PRICE: &str = "E131: oracle price missing or stale";
+pub const ERR132_MIN_AMOUNT_OUT_BELOW_ORACLE: &str = "E132: min_amount_out below the oracle price minus max slippage";
+pub const ERR133_PRICE_ABOVE_MAX_PRICE: &str = "E133: implied price of min_amount_out above the max price of the round";
+pub const ERR134_INVALID_MAX_PRICE: &str = "E134: max price must be greater than 0";
+pub const ERR135_ROUTE_NOT_FOUND: &str = "E135: registered route not found";
+pub const ERR136_KEEPER_NOT_ENABLED: &str = "E136: keeper buyback not enabled for the fund token";
+pub const ERR137_INVALID_KEEPER_BOUNTY: &str = "E137: keeper bounty must be less than 10000 bps";
+pub const ERR138_KEEPER_PRICE_UNBOUNDED: &str = "E138: keeper buyback needs a max price on the round or a price oracle";
+pub const ERR139_POOL_NOT_WHITELISTED: &str = "E139: pool not in the pool white list";
+pub const ERR140_POOL_LIQUIDITY_TOO_LOW: &str = "E140: pool reserve below the min pool liquidity";
+pub const ERR141_TOO_MANY_SPLIT_ROUTES: &str = "E141: split buyback has too many routes or swaps";
+pub const ERR142_SPLIT_FUND_TOKEN_MISMATCH: &str = "E142: routes of a split buyback spend different fund tokens";
+pub const ERR143_INVALID_RECIPIENT_WEIGHTS: &str = "E143: recipient weights must add up to 10000 bps";
+pub const ERR144_INVALID_RECIPIENTS: &str = "E144: recipients must be 1 to 10 distinct accounts";
+pub const ERR145_RECIPIENT_OWED: &str = "E145: recipient is still owed a share";
+pub const ERR146_RECIPIENTS_GAS_EXCEEDED: &str = "E146: recipients need more gas to deliver than distribute can attach";
+pub const ERR147_NOTHING_VESTED: &str = "E147: nothing vested to claim";
+pub const ERR148_CLAIM_IN_PROGRESS: &str = "E148: a claim of the account is in progress";
+pub const ERR149_NOTHING_TO_CLAIM: &str = "E149: nothing to claim";
+pub const ERR150_KEEPER_AMOUNT_IN_TOO_SMALL: &str = "E150: amount_in below the keeper min amount_in of the fund token";
+pub const ERR151_REGISTERED_ROUTES_ONLY: &str = "E151: guardians may only buy back along registered routes";
+pub const ERR152_TRANSFERS_IN_FLIGHT: &str = "E152: buyback token transfers are in flight, which the buyback would count as received";
+pub const ERR153_NOTHING_LEFT_TO_RELEASE: &str = "E153: the round has nothing left to release over its schedule, top it up keeping the end time";
+pub const ERR154_SPENT_EXCEEDS_LOCKED: &str = "E154: spent amount exceeds the fund the pending buyback locked";
\ No newline at end of file
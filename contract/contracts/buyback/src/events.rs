@@ -0,0 +1,132 @@
+use crate::*;
+
+pub mod emit {
+    use super::*;
+    use near_sdk::serde_json::json;
+
+    #[derive(Serialize)]
+    #[serde(crate = "near_sdk::serde")]
+    struct CancelRound<'a> {
+        pub round_id: u64,
+        pub fund_token_id: &'a AccountId,
+        pub receiver_id: &'a AccountId,
+        pub refund_amount: U128,
+    }
+
+    #[derive(Serialize)]
+    #[serde(crate = "near_sdk::serde")]
+    struct TopUpRound<'a> {
+        pub round_id: u64,
+        pub fund_token_id: &'a AccountId,
+        pub amount: U128,
+        pub end_time: u32,
+    }
+
+    #[derive(Serialize)]
+    #[serde(crate = "near_sdk::serde")]
+    struct RollOverRound<'a> {
+        pub from_round_id: u64,
+        pub to_round_id: u64,
+        pub fund_token_id: &'a AccountId,
+        pub amount: U128,
+    }
+
+    #[derive(Serialize)]
+    #[serde(crate = "near_sdk::serde")]
+    struct Buyback<'a> {
+        pub execution_id: u64,
+        pub round_id: u64,
+        pub fund_token_id: &'a AccountId,
+        pub amount_in: U128,
+        pub cost: U128,
+        pub received_amount: U128,
+        pub oracle_price: Option<U128>,
+        pub keeper_id: &'a Option<AccountId>,
+        pub bounty: U128,
+    }
+
+    #[derive(Serialize)]
+    #[serde(crate = "near_sdk::serde")]
+    struct Burn<'a> {
+        pub recipient_id: &'a AccountId,
+        pub token_id: &'a AccountId,
+        pub amount: U128,
+        pub total_burned: U128,
+    }
+
+    fn log_event<T: Serialize>(event: &str, data: T) {
+        let event = json!({
+            "standard": "buyback",
+            "version": "1.0.0",
+            "event": event,
+            "data": [data]
+        });
+
+        log!("EVENT_JSON:{}", event.to_string());
+    }
+
+    pub fn cancel_round(round_id: u64, fund_token_id: &AccountId, receiver_id: &AccountId, refund_amount: Balance) {
+        log_event(
+            "cancel_round",
+            CancelRound {
+                round_id,
+                fund_token_id,
+                receiver_id,
+                refund_amount: U128(refund_amount),
+            },
+        );
+    }
+
+    pub fn top_up_round(round_id: u64, fund_token_id: &AccountId, amount: Balance, end_time: u32) {
+        log_event(
+            "top_up_round",
+            TopUpRound {
+                round_id,
+                fund_token_id,
+                amount: U128(amount),
+                end_time,
+            },
+        );
+    }
+
+    pub fn roll_over_round(from_round_id: u64, to_round_id: u64, fund_token_id: &AccountId, amount: Balance) {
+        log_event(
+            "roll_over_round",
+            RollOverRound {
+                from_round_id,
+                to_round_id,
+                fund_token_id,
+                amount: U128(amount),
+            },
+        );
+    }
+
+    pub fn buyback(execution_id: u64, execution: &Execution) {
+        log_event(
+            "buyback",
+            Buyback {
+                execution_id,
+                round_id: execution.round_id,
+                fund_token_id: &execution.fund_token_id,
+                amount_in: U128(execution.amount_in),
+                cost: U128(execution.cost),
+                received_amount: U128(execution.received_amount),
+                oracle_price: execution.oracle_price.map(U128),
+                keeper_id: &execution.keeper_id,
+                bounty: U128(execution.bounty),
+            },
+        );
+    }
+
+    pub fn burn(recipient_id: &AccountId, token_id: &AccountId, amount: Balance, total_burned: Balance) {
+        log_event(
+            "burn",
+            Burn {
+                recipient_id,
+                token_id,
+                amount: U128(amount),
+                total_burned: U128(total_burned),
+            },
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use crate::*;
+
+/// A cross contract operation that reads the buyback token balance of this contract,
+/// only one of them may run at a time.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Eq, PartialEq, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum PendingOperation {
+    Buyback,
+    Distribute,
+}
+
+/// The fund locked by the do_buyback in progress until the exchange reports what it used.
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct PendingBuyback {
+    pub round_id: u64,
+    pub amount_in: u128,
+    // unit: sec
+    pub spend_time: u32,
+}
+
+/// A do_buyback the exchange has spent some of the fund on.
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct Execution {
+    pub round_id: u64,
+    pub fund_token_id: AccountId,
+    pub amount_in: u128,
+    pub cost: u128,
+    // buyback token balance increase across the swap
+    pub received_amount: u128,
+    // unit: sec
+    pub time: u32,
+    // fund token per buyback token implied by the oracle at the swap, scaled by PRICE_PRECISION
+    pub oracle_price: Option<u128>,
+    // the caller of keeper_buyback and the bounty it was paid, None for do_buyback
+    pub keeper_id: Option<AccountId>,
+    pub bounty: u128,
+}
+
+impl Contract {
+    pub fn internal_start_operation(&mut self, operation: PendingOperation) {
+        require!(self.data().pending_operation.is_none(), ERR119_OPERATION_IN_PROGRESS);
+        self.data_mut().pending_operation = Some(operation);
+    }
+
+    /// A buyback measures what it received by the buyback token balance, which a claim sent meanwhile would change.
+    pub fn assert_no_buyback_pending(&self) {
+        require!(self.data().pending_operation != Some(PendingOperation::Buyback), ERR119_OPERATION_IN_PROGRESS);
+    }
+
+    /// Whether buyback token transfers that would change the balance a buyback measures are in flight.
+    pub fn internal_transfers_in_flight(&self) -> bool {
+        self.data().delivering_amount > 0 || self.data().claiming_amount > 0
+    }
+
+    pub fn internal_finish_operation(&mut self) {
+        self.data_mut().pending_operation = None;
+    }
+
+    /// Give back the fund a do_buyback has locked, when the swap never started.
+    pub fn internal_abort_buyback(&mut self, round_id: u64, amount_in: u128, spend_time: u32) {
+        let mut round = self.internal_get_round(round_id);
+        round.fund_locked -= amount_in;
+        self.internal_set_round(round_id, &round);
+        self.data_mut().pending_buyback = None;
+        self.internal_refund_spend(&round.fund_token_id, spend_time, amount_in);
+        self.internal_release_pending_bounty();
+        self.internal_finish_operation();
+    }
+
+    pub fn internal_get_execution(&self, execution_id: u64) -> Execution {
+        self.data().executions.get(execution_id).expect(ERR120_EXECUTION_NOT_FOUND)
+    }
+}
@@ -0,0 +1,268 @@
+use crate::*;
+use near_sdk::is_promise_success;
+
+pub const GAS_FOR_PAY_BOUNTY_CALLBACK: Gas = Gas(10 * Gas::ONE_TERA.0);
+
+/// What a keeper is paid for a keeper_buyback of a fund token, in that fund token.
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct KeeperBounty {
+    // share of amount_in, in bps
+    pub bounty_bps: u32,
+    pub max_bounty: u128,
+    // smallest amount_in keeper_buyback takes, so the bounty can't be farmed with dust buybacks
+    pub min_amount_in: u128,
+}
+
+impl KeeperBounty {
+    pub fn bounty(&self, amount_in: u128) -> u128 {
+        std::cmp::min(ratio(amount_in, self.bounty_bps), self.max_bounty)
+    }
+}
+
+/// The bounty locked by the keeper_buyback in progress.
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct PendingBounty {
+    pub keeper_id: AccountId,
+    pub round_id: u64,
+    pub amount: u128,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct KeeperTokenStats {
+    pub fund_token_id: AccountId,
+    pub executions: u32,
+    pub cost: U128,
+    pub bounty_paid: U128,
+}
+
+/// What a keeper has done with keeper_buyback, per fund token.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct KeeperStats {
+    pub tokens: Vec<KeeperTokenStats>,
+    // unit: sec
+    pub last_execution_time: u32,
+}
+
+impl KeeperStats {
+    pub fn token_stats_mut(&mut self, fund_token_id: &AccountId) -> &mut KeeperTokenStats {
+        let index = match self.tokens.iter().position(|stats| stats.fund_token_id == *fund_token_id) {
+            Some(index) => index,
+            None => {
+                self.tokens.push(KeeperTokenStats {
+                    fund_token_id: fund_token_id.clone(),
+                    executions: 0,
+                    cost: U128(0),
+                    bounty_paid: U128(0),
+                });
+                self.tokens.len() - 1
+            }
+        };
+        &mut self.tokens[index]
+    }
+}
+
+impl Contract {
+    /// Unlock the bounty of the keeper_buyback in progress, if any, and return it.
+    pub fn internal_release_pending_bounty(&mut self) -> Option<PendingBounty> {
+        let pending_bounty = self.data_mut().pending_bounty.take()?;
+        let mut round = self.internal_get_round(pending_bounty.round_id);
+        round.fund_locked -= pending_bounty.amount;
+        self.internal_set_round(pending_bounty.round_id, &round);
+        Some(pending_bounty)
+    }
+
+    /// Record a keeper_buyback the exchange spent `cost` on and send the keeper its bounty.
+    pub fn internal_reward_keeper(&mut self, execution_id: u64, fund_token_id: &AccountId, cost: u128) {
+        let pending_bounty = match self.data_mut().pending_bounty.take() {
+            Some(pending_bounty) => pending_bounty,
+            None => return,
+        };
+        let mut keeper_stats = self.data().keeper_stats.get(&pending_bounty.keeper_id).unwrap_or_default();
+        keeper_stats.last_execution_time = nano_to_sec(env::block_timestamp());
+        let token_stats = keeper_stats.token_stats_mut(fund_token_id);
+        token_stats.executions += 1;
+        token_stats.cost = U128(token_stats.cost.0 + cost);
+        self.data_mut().keeper_stats.insert(&pending_bounty.keeper_id, &keeper_stats);
+        if pending_bounty.amount == 0 {
+            return;
+        }
+        // the bounty stays locked until the transfer resolves
+        ext_fungible_token::ext(fund_token_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(
+                pending_bounty.keeper_id.clone(),
+                U128(pending_bounty.amount),
+                None
+            ).then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_PAY_BOUNTY_CALLBACK)
+                    .callback_pay_bounty(execution_id, pending_bounty.keeper_id, pending_bounty.round_id, U128(pending_bounty.amount))
+            );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Set the bounty of keeper_buyback for a fund token, which also opens keeper_buyback to anyone for it. Only can be called by owner.
+    #[payable]
+    pub fn set_keeper_bounty(&mut self, token_id: AccountId, bounty_bps: u32, max_bounty: U128, min_amount_in: U128) {
+        assert_one_yocto();
+        self.assert_owner();
+        require!(bounty_bps < MAX_RATIO, ERR137_INVALID_KEEPER_BOUNTY);
+        self.data_mut().keeper_bounties.insert(&token_id, &KeeperBounty { bounty_bps, max_bounty: max_bounty.0, min_amount_in: min_amount_in.0 });
+    }
+
+    /// Close keeper_buyback for a fund token. Only can be called by owner.
+    #[payable]
+    pub fn remove_keeper_bounty(&mut self, token_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        require!(self.data_mut().keeper_bounties.remove(&token_id).is_some(), ERR136_KEEPER_NOT_ENABLED);
+    }
+
+    /// do_buyback along a registered route that anyone can call for a fund token with a keeper bounty.
+    /// The round needs a max price or the contract a fresh oracle price, amount_in at least the min amount_in
+    /// of the bounty, and the caller is paid the bounty from the fund once the swap went through.
+    pub fn keeper_buyback(&mut self, route_id: u32, amount_in: U128, min_amount_out: U128) {
+        self.assert_contract_running();
+        let route_template = self.internal_get_route_template(route_id);
+        let fund_token_id = route_template.fund_token_id.clone();
+        let keeper_bounty = self.data().keeper_bounties.get(&fund_token_id).expect(ERR136_KEEPER_NOT_ENABLED);
+        require!(amount_in.0 >= keeper_bounty.min_amount_in, ERR150_KEEPER_AMOUNT_IN_TOO_SMALL);
+        // checked against the round do_buyback will spend, which rolls ended rounds over first
+        self.internal_roll_over_ended_rounds(&fund_token_id);
+        let round_id = self.internal_active_round_id(&fund_token_id).expect(ERR103_NO_ACTIVE_ROUND);
+        let round = self.internal_get_round(round_id);
+        // the quote alone can be moved by the caller, so keepers need a bound that doesn't depend on the pool
+        require!(round.max_price.is_some() || self.internal_oracle_prices(&fund_token_id).is_some(), ERR138_KEEPER_PRICE_UNBOUNDED);
+        let bounty = keeper_bounty.bounty(amount_in.0);
+        let available_fund_amount = round.available_fund_amount(self.internal_schedule_time());
+        require!(amount_in.0.saturating_add(bounty) <= available_fund_amount, "Invalid amount_in");
+        self.internal_do_buyback(vec![route_template.to_swap_msg(amount_in.0, min_amount_out.0)]);
+
+        let mut round = self.internal_get_round(round_id);
+        round.fund_locked += bounty;
+        self.internal_set_round(round_id, &round);
+        self.data_mut().pending_bounty = Some(PendingBounty {
+            keeper_id: env::predecessor_account_id(),
+            round_id,
+            amount: bounty,
+        });
+    }
+
+    #[private]
+    pub fn callback_pay_bounty(&mut self, execution_id: u64, keeper_id: AccountId, round_id: u64, bounty: U128) {
+        let mut round = self.internal_get_round(round_id);
+        round.fund_locked -= bounty.0;
+        if is_promise_success() {
+            round.fund_cost += bounty.0;
+            let mut keeper_stats = self.data().keeper_stats.get(&keeper_id).unwrap_or_default();
+            let token_stats = keeper_stats.token_stats_mut(&round.fund_token_id);
+            token_stats.bounty_paid = U128(token_stats.bounty_paid.0 + bounty.0);
+            self.data_mut().keeper_stats.insert(&keeper_id, &keeper_stats);
+        } else {
+            let mut execution = self.internal_get_execution(execution_id);
+            execution.bounty = 0;
+            self.data_mut().executions.replace(execution_id, &execution);
+            log!("Failed to pay bounty {} to keeper {}", bounty.0, keeper_id);
+        }
+        self.internal_set_round(round_id, &round);
+        self.internal_try_finish_active_round(&round.fund_token_id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::test::*;
+    use near_sdk::PromiseResult;
+
+    #[test]
+    fn bounty_cap() {
+        let keeper_bounty = KeeperBounty { bounty_bps: 10, max_bounty: 500, min_amount_in: 0 };
+        assert_eq!(keeper_bounty.bounty(100_000), 100);
+        assert_eq!(keeper_bounty.bounty(10_000_000), 500);
+    }
+
+    pub fn keeper_id() -> AccountId {
+        AccountId::new_unchecked("keeper".to_string())
+    }
+
+    #[test]
+    fn keeper_bounty() {
+        let (mut context, mut contract) = setup_contract();
+        let route_id = contract.register_route(nusdt(), vec![RouteHop { pool_id: 0, token_out: buyback_token_id() }]);
+        // 0.1% of amount_in, at most 0.02 nusdt, for at least 1 nusdt
+        contract.set_keeper_bounty(nusdt(), 10, U128(d(2, 4)), U128(d(1, 6)));
+        fund_round(&mut contract, &mut context, buyback_info_with_max_price(1000, 100, 10, d(50, 6)));
+
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(keeper_id()).build());
+        contract.keeper_buyback(route_id, U128(d(10, 6)), U128(MIN_AMOUNT_OUT / 4));
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(90, 6) - d(1, 4));
+        let swap_msg = contract.internal_get_route_template(route_id).to_swap_msg(d(10, 6), MIN_AMOUNT_OUT / 4);
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(MIN_AMOUNT_OUT / 4)).unwrap()));
+        contract.callback_quote_buyback(0, U128(d(10, 6)), 1100, vec![swap_msg.clone()], 0, U128(0));
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(BALANCE_BEFORE)).unwrap()));
+        contract.callback_start_buyback(0, U128(d(10, 6)), 1100, vec![swap_msg]);
+        finish_buyback(&mut contract, &mut context, 0, d(10, 6), Some(d(10, 6)), MIN_AMOUNT_OUT / 4);
+        let execution = contract.get_execution(0).unwrap();
+        assert_eq!((execution.keeper_id, execution.bounty.0), (Some(keeper_id()), d(1, 4)));
+        callback_with_result(&context, PromiseResult::Successful(vec![]));
+        contract.callback_pay_bounty(0, keeper_id(), 0, U128(d(1, 4)));
+        assert_eq!(contract.get_active_round(nusdt()).unwrap().fund_cost.0, d(10, 6) + d(1, 4));
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(90, 6) - d(1, 4));
+        let keeper_stats = contract.get_keeper_stats(keeper_id()).unwrap();
+        assert_eq!(keeper_stats.last_execution_time, 1100);
+        assert_eq!((keeper_stats.tokens[0].executions, keeper_stats.tokens[0].cost.0, keeper_stats.tokens[0].bounty_paid.0), (1, d(10, 6), d(1, 4)));
+
+        // the bounty is capped, and unlocked again with the fund when the swap fails
+        testing_env!(context.predecessor_account_id(keeper_id()).build());
+        contract.keeper_buyback(route_id, U128(d(40, 6)), U128(MIN_AMOUNT_OUT));
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(50, 6) - d(3, 4));
+        finish_buyback(&mut contract, &mut context, 0, d(40, 6), None, 0);
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(90, 6) - d(1, 4));
+        assert_eq!(contract.list_keepers(None, None).len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "E138")]
+    fn keeper_price_unbounded() {
+        let (mut context, mut contract) = setup_contract();
+        let route_id = contract.register_route(nusdt(), vec![RouteHop { pool_id: 0, token_out: buyback_token_id() }]);
+        contract.set_keeper_bounty(nusdt(), 10, U128(d(2, 4)), U128(d(1, 6)));
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(keeper_id()).build());
+        contract.keeper_buyback(route_id, U128(d(10, 6)), U128(MIN_AMOUNT_OUT));
+    }
+
+    #[test]
+    #[should_panic(expected = "E150")]
+    fn keeper_amount_in_too_small() {
+        let (mut context, mut contract) = setup_contract();
+        let route_id = contract.register_route(nusdt(), vec![RouteHop { pool_id: 0, token_out: buyback_token_id() }]);
+        contract.set_keeper_bounty(nusdt(), 10, U128(d(2, 4)), U128(d(1, 6)));
+        fund_round(&mut contract, &mut context, buyback_info_with_max_price(1000, 100, 10, d(50, 6)));
+        // a dust buyback would still earn the bounty
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(keeper_id()).build());
+        contract.keeper_buyback(route_id, U128(d(1, 5)), U128(MIN_AMOUNT_OUT / 400));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid amount_in")]
+    fn keeper_bounty_exceeds_available() {
+        let (mut context, mut contract) = setup_contract();
+        let route_id = contract.register_route(nusdt(), vec![RouteHop { pool_id: 0, token_out: buyback_token_id() }]);
+        contract.set_keeper_bounty(nusdt(), 10, U128(d(2, 4)), U128(d(1, 6)));
+        fund_round(&mut contract, &mut context, buyback_info_with_max_price(1000, 100, 10, d(50, 6)));
+        // all of the fund leaves nothing for the bounty
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(keeper_id()).build());
+        contract.keeper_buyback(route_id, U128(d(100, 6)), U128(MIN_AMOUNT_OUT * 5 / 2));
+    }
+}
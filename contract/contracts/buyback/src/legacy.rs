@@ -0,0 +1,224 @@
+//! This module captures all the code needed to migrate from previous version.
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractDataV1000 {
+    pub owner_id: AccountId,
+    pub ref_exchange_id: AccountId,
+    pub burn_account_id: AccountId,
+    pub company_account_id: AccountId,
+    pub reward_account_id: AccountId,
+    pub buyback_token_id: AccountId,
+    pub token_white_list: UnorderedSet<AccountId>,
+    pub guardians: UnorderedSet<AccountId>,
+    pub burn_rate: u32,
+    pub company_rate: u32,
+    pub reward_rate: u32,
+    pub state: RunningState,
+
+    // unit: sec
+    pub total_buyback_time: u32,
+    // unit: sec
+    pub buyback_internal: u32,
+    // unit: sec
+    pub current_round_start_time: u32,
+    pub current_round_fund_token_id: AccountId,
+    pub current_round_fund_amount: u128,
+    pub current_round_fund_cost: u128,
+
+    pub belong_burn_amount: u128,
+    pub belong_company_amount: u128,
+    pub belong_reward_amount: u128,
+}
+
+/// The burn, company and reward accounts of V1000 as recipients, each with (account, weight, owed amount).
+pub fn legacy_recipients(accounts: [(AccountId, u32, u128); 3]) -> Vec<Recipient> {
+    ["burn", "company", "reward"].iter().zip(accounts).map(|(label, (account_id, weight_bps, owed_amount))| Recipient {
+        account_id,
+        weight_bps,
+        label: label.to_string(),
+        delivery_mode: DeliveryMode::Transfer,
+        owed_amount,
+    }).collect()
+}
+
+impl From<ContractDataV1000> for ContractData {
+    fn from(a: ContractDataV1000) -> Self {
+        let ContractDataV1000 {
+            owner_id,
+            ref_exchange_id,
+            burn_account_id,
+            company_account_id,
+            reward_account_id,
+            buyback_token_id,
+            token_white_list,
+            guardians,
+            burn_rate,
+            company_rate,
+            reward_rate,
+            state,
+            total_buyback_time,
+            buyback_internal,
+            current_round_start_time,
+            current_round_fund_token_id,
+            current_round_fund_amount,
+            current_round_fund_cost,
+            belong_burn_amount,
+            belong_company_amount,
+            belong_reward_amount,
+        } = a;
+
+        let mut rounds = Vector::new(StorageKeys::Rounds);
+        let mut round_queues = UnorderedMap::new(StorageKeys::RoundQueues);
+        // keep the round that was running before the upgrade as round 0
+        if current_round_fund_amount > 0 {
+            let mut round = Round::new(current_round_fund_token_id.clone(), current_round_fund_amount, current_round_start_time, total_buyback_time, buyback_internal, ReleaseCurve::Stepwise);
+            round.fund_cost = current_round_fund_cost;
+            if round.is_fully_spent() {
+                round.close(RoundStatus::Finished);
+            } else {
+                round_queues.insert(&current_round_fund_token_id, &vec![0]);
+            }
+            rounds.push(&round);
+        }
+        // the schedule of a paused contract is frozen from the upgrade on
+        let paused_at = if state == RunningState::Paused {
+            Some(nano_to_sec(env::block_timestamp()))
+        } else {
+            None
+        };
+
+        Self {
+            owner_id,
+            ref_exchange_id,
+            buyback_token_id,
+            token_white_list,
+            guardians,
+            state,
+            rounds,
+            round_queues,
+            paused_at,
+            spend_caps: UnorderedMap::new(StorageKeys::SpendCaps),
+            spend_records: UnorderedMap::new(StorageKeys::SpendRecords),
+            executions: Vector::new(StorageKeys::Executions),
+            pending_operation: None,
+            pending_buyback: None,
+            max_slippage_bps: DEFAULT_MAX_SLIPPAGE_BPS,
+            price_oracle_id: None,
+            max_price_age: DEFAULT_MAX_PRICE_AGE,
+            oracle_prices: UnorderedMap::new(StorageKeys::OraclePrices),
+            route_templates: UnorderedMap::new(StorageKeys::RouteTemplates),
+            next_route_id: 0,
+            registered_routes_only: false,
+            keeper_bounties: UnorderedMap::new(StorageKeys::KeeperBounties),
+            keeper_stats: UnorderedMap::new(StorageKeys::KeeperStats),
+            pending_bounty: None,
+            pool_white_list: UnorderedSet::new(StorageKeys::PoolWhiteList),
+            min_pool_liquidity: UnorderedMap::new(StorageKeys::MinPoolLiquidity),
+            recipients: legacy_recipients([
+                (burn_account_id, burn_rate, belong_burn_amount),
+                (company_account_id, company_rate, belong_company_amount),
+                (reward_account_id, reward_rate, belong_reward_amount),
+            ]),
+            delivering_amount: 0,
+            total_burned: 0,
+            vestings: UnorderedMap::new(StorageKeys::Vestings),
+            total_vesting_amount: 0,
+            claimable_amounts: UnorderedMap::new(StorageKeys::ClaimableAmounts),
+            total_claimable_amount: 0,
+            claiming_amount: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::test::*;
+    use near_sdk::test_utils::VMContextBuilder;
+
+    #[test]
+    fn migrate_v1000() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.current_account_id(contract_account_id()).block_timestamp(sec_to_nano(2000)).build());
+        let mut token_white_list = UnorderedSet::new(StorageKeys::TokenWhiteList);
+        token_white_list.insert(&nusdt());
+        let mut guardians = UnorderedSet::new(StorageKeys::Guardian);
+        guardians.insert(&guardian_id());
+        env::state_write(&Contract {
+            data: VersionedContractData::V1000(ContractDataV1000 {
+                owner_id: owner_id(),
+                ref_exchange_id: AccountId::new_unchecked("ref_exchange".to_string()),
+                burn_account_id: burn_account_id(),
+                company_account_id: company_account_id(),
+                reward_account_id: reward_account_id(),
+                buyback_token_id: buyback_token_id(),
+                token_white_list,
+                guardians,
+                burn_rate: 5000,
+                company_rate: 3000,
+                reward_rate: 2000,
+                state: RunningState::Running,
+                total_buyback_time: 3600,
+                buyback_internal: 60,
+                current_round_start_time: 1000,
+                current_round_fund_token_id: nusdt(),
+                current_round_fund_amount: d(100, 6),
+                current_round_fund_cost: d(40, 6),
+                belong_burn_amount: d(1, 18),
+                belong_company_amount: d(2, 18),
+                belong_reward_amount: 0,
+            })
+        });
+
+        let contract = Contract::migrate();
+        let metadata = contract.get_metadata();
+        assert_eq!(metadata.owner, owner_id());
+        assert_eq!(metadata.ref_exchange_id, AccountId::new_unchecked("ref_exchange".to_string()));
+        assert_eq!(metadata.token_white_list, vec![nusdt()]);
+        assert_eq!(metadata.guardians, vec![guardian_id()]);
+        assert_eq!(metadata.paused_at, None);
+        assert!(metadata.pending_operation.is_none());
+        assert_eq!(metadata.max_slippage_bps, DEFAULT_MAX_SLIPPAGE_BPS);
+        assert_eq!(metadata.price_oracle_id, None);
+        assert_eq!(metadata.max_price_age, DEFAULT_MAX_PRICE_AGE);
+        assert!(!metadata.registered_routes_only);
+        assert!(metadata.keeper_bounties.is_empty());
+        assert!(metadata.pool_white_list.is_empty());
+        assert_eq!(metadata.delivering_amount.0, 0);
+        assert_eq!(metadata.total_burned.0, 0);
+        assert_eq!(metadata.total_vesting_amount.0, 0);
+        assert_eq!(metadata.total_claimable_amount.0, 0);
+        assert_eq!(metadata.claiming_amount.0, 0);
+
+        // the running round is kept as round 0
+        assert_eq!(metadata.round_queues.get(&nusdt()), Some(&vec![0]));
+        let round = contract.get_round(0).unwrap();
+        assert_eq!(round.fund_token_id, nusdt());
+        assert_eq!(round.fund_amount.0, d(100, 6));
+        assert_eq!(round.fund_cost.0, d(40, 6));
+        assert_eq!(round.fund_locked.0, 0);
+        assert_eq!(round.start_time, 1000);
+        assert_eq!(round.total_buyback_time, 3600);
+        assert_eq!(round.buyback_internal, 60);
+        assert_eq!(round.release_curve, ReleaseCurve::Stepwise);
+        assert_eq!(round.status, RoundStatus::Queued);
+        assert!(contract.get_round(1).is_none());
+
+        // the three accounts become recipients, each with what it was owed
+        let recipients = contract.get_recipients();
+        assert_eq!(recipients.len(), 3);
+        let expected = [
+            (burn_account_id(), 5000, "burn", d(1, 18)),
+            (company_account_id(), 3000, "company", d(2, 18)),
+            (reward_account_id(), 2000, "reward", 0),
+        ];
+        for (recipient, (account_id, weight_bps, label, owed_amount)) in recipients.iter().zip(expected.iter()) {
+            assert_eq!(&recipient.account_id, account_id);
+            assert_eq!(recipient.weight_bps, *weight_bps);
+            assert_eq!(recipient.label, *label);
+            assert_eq!(recipient.delivery_mode, DeliveryMode::Transfer);
+            assert_eq!(recipient.owed_amount.0, *owed_amount);
+        }
+    }
+}
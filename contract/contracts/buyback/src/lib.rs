@@ -1,6 +1,6 @@
 use std::fmt;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::UnorderedSet;
+use near_sdk::collections::{UnorderedMap, UnorderedSet, Vector};
 use near_sdk::json_types::U128;
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
@@ -9,23 +9,66 @@ use near_sdk::{
 };
 
 mod action;
+mod curve;
 mod errors;
+mod events;
+mod execution;
+mod keeper;
+mod legacy;
+mod oracle;
 mod owner;
+mod pool;
+mod recipient;
+mod round;
+mod route;
+mod route_template;
+mod schedule;
+mod simulation;
+mod spend_cap;
 mod token_receiver;
 mod view;
+mod vesting;
 mod utils;
 
 pub use action::*;
+pub use curve::*;
 pub use errors::*;
+pub use events::*;
+pub use execution::*;
+pub use keeper::*;
+pub use legacy::*;
+pub use oracle::*;
 pub use owner::*;
+pub use pool::*;
+pub use recipient::*;
+pub use round::*;
+pub use route::*;
+pub use route_template::*;
+pub use schedule::*;
+pub use simulation::*;
+pub use spend_cap::*;
 pub use token_receiver::*;
 pub use view::*;
+pub use vesting::*;
 pub use utils::*;
 
 #[derive(BorshSerialize, BorshStorageKey)]
 enum StorageKeys {
     TokenWhiteList,
-    Guardian
+    Guardian,
+    Rounds,
+    RoundQueues,
+    SpendCaps,
+    SpendRecords,
+    Executions,
+    OraclePrices,
+    RouteTemplates,
+    KeeperBounties,
+    KeeperStats,
+    PoolWhiteList,
+    MinPoolLiquidity,
+    Vestings,
+    ClaimableAmounts,
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Eq, PartialEq, Clone)]
@@ -48,36 +91,69 @@ impl fmt::Display for RunningState {
 pub struct ContractData {
     pub owner_id: AccountId,
     pub ref_exchange_id: AccountId,
-    pub burn_account_id: AccountId, 
-    pub company_account_id: AccountId, 
-    pub reward_account_id: AccountId,
     pub buyback_token_id: AccountId,
     pub token_white_list: UnorderedSet<AccountId>,
     pub guardians: UnorderedSet<AccountId>,
-    pub burn_rate: u32,
-    pub company_rate: u32,
-    pub reward_rate: u32,
     pub state: RunningState,
 
-    // unit: sec
-    pub total_buyback_time: u32,
-    // unit: sec
-    pub buyback_internal: u32,
-    // unit: sec
-    pub current_round_start_time: u32,
-    pub current_round_fund_token_id: AccountId,
-    pub current_round_fund_amount: u128,
-    pub current_round_fund_cost: u128,
-
-    pub belong_burn_amount: u128,
-    pub belong_company_amount: u128,
-    pub belong_reward_amount: u128,
-
+    // all rounds ever created, indexed by round id
+    pub rounds: Vector<Round>,
+    // per fund token, ids of unfinished rounds in the order they are consumed
+    pub round_queues: UnorderedMap<AccountId, Vec<u64>>,
+    // when the contract was paused, None while running. unit: sec
+    pub paused_at: Option<u32>,
+    // per fund token limits on do_buyback, a token without one is unlimited
+    pub spend_caps: UnorderedMap<AccountId, SpendCap>,
+    pub spend_records: UnorderedMap<AccountId, SpendRecord>,
+    // all do_buyback the exchange has spent fund on, indexed by execution id
+    pub executions: Vector<Execution>,
+    pub pending_operation: Option<PendingOperation>,
+    // fund locked by the do_buyback in progress, until its swap resolves
+    pub pending_buyback: Option<PendingBuyback>,
+    // how far min_amount_out of do_buyback may be below the exchange quote, in bps
+    pub max_slippage_bps: u32,
+    // do_buyback is also checked against the prices of this oracle when set
+    pub price_oracle_id: Option<AccountId>,
+    // how old a cached oracle price may be for do_buyback. unit: sec
+    pub max_price_age: u32,
+    pub oracle_prices: UnorderedMap<AccountId, CachedPrice>,
+    // pool paths execute_registered_buyback may swap along, indexed by route id
+    pub route_templates: UnorderedMap<u32, RouteTemplate>,
+    pub next_route_id: u32,
+    // guardians may only buy back with execute_registered_buyback when set, do_buyback is left to the owner
+    pub registered_routes_only: bool,
+    // per fund token bounty of keeper_buyback, a token without one is closed to keepers
+    pub keeper_bounties: UnorderedMap<AccountId, KeeperBounty>,
+    pub keeper_stats: UnorderedMap<AccountId, KeeperStats>,
+    // bounty locked by the keeper_buyback in progress
+    pub pending_bounty: Option<PendingBounty>,
+    // pools do_buyback may swap in, any pool while empty
+    pub pool_white_list: UnorderedSet<u64>,
+    // per token, how much of it a pool must hold for do_buyback to swap it there
+    pub min_pool_liquidity: UnorderedMap<AccountId, u128>,
+    // who distribute hands the buyback token out to, in order
+    pub recipients: Vec<Recipient>,
+    // shares distribute has sent out and whose delivery hasn't resolved yet
+    pub delivering_amount: u128,
+    // buyback token burned by distribute with the token's burn
+    pub total_burned: u128,
+    // vesting schedules of the recipients with vesting delivery, per beneficiary
+    pub vestings: UnorderedMap<AccountId, Vesting>,
+    // buyback token locked in vestings and not claimed yet, including claims being transferred
+    pub total_vesting_amount: u128,
+    // buyback token credited to the recipients with claim delivery, per account
+    pub claimable_amounts: UnorderedMap<AccountId, u128>,
+    // buyback token credited and not claimed yet, including claims being transferred
+    pub total_claimable_amount: u128,
+    // claim and claim_vested transfers whose result hasn't come back yet
+    pub claiming_amount: u128,
 }
 
 #[derive(BorshSerialize, BorshDeserialize)]
+#[allow(clippy::large_enum_variant)]
 pub enum VersionedContractData {
-    V1000(ContractData),
+    V1000(ContractDataV1000),
+    V1001(ContractData),
 }
 
 #[near_bindgen]
@@ -92,31 +168,46 @@ impl Contract {
     pub fn new(owner_id: AccountId, burn_account_id: AccountId, company_account_id: AccountId, reward_account_id: AccountId, buyback_token_id: AccountId) -> Self {
         require!(!env::state_exists(), E000_ALREADY_INIT);
         Self {
-            data: VersionedContractData::V1000(ContractData {
+            data: VersionedContractData::V1001(ContractData {
                 owner_id: owner_id.clone(),
-                ref_exchange_id: owner_id.clone(),
-                burn_account_id, 
-                company_account_id, 
-                reward_account_id,
+                ref_exchange_id: owner_id,
                 buyback_token_id,
                 token_white_list: UnorderedSet::new(StorageKeys::TokenWhiteList), 
                 guardians: UnorderedSet::new(StorageKeys::Guardian), 
                 
-                burn_rate: 0,
-                company_rate: 0,
-                reward_rate: 0,
                 state: RunningState::Running,
 
-                total_buyback_time: 0,
-                buyback_internal: 0,
-                current_round_start_time: 0,
-                current_round_fund_token_id: owner_id,
-                current_round_fund_amount: 0,
-                current_round_fund_cost: 0,
-
-                belong_burn_amount: 0,
-                belong_company_amount: 0,
-                belong_reward_amount: 0,
+                rounds: Vector::new(StorageKeys::Rounds),
+                round_queues: UnorderedMap::new(StorageKeys::RoundQueues),
+                paused_at: None,
+                spend_caps: UnorderedMap::new(StorageKeys::SpendCaps),
+                spend_records: UnorderedMap::new(StorageKeys::SpendRecords),
+                executions: Vector::new(StorageKeys::Executions),
+                pending_operation: None,
+                pending_buyback: None,
+                max_slippage_bps: DEFAULT_MAX_SLIPPAGE_BPS,
+                price_oracle_id: None,
+                max_price_age: DEFAULT_MAX_PRICE_AGE,
+                oracle_prices: UnorderedMap::new(StorageKeys::OraclePrices),
+                route_templates: UnorderedMap::new(StorageKeys::RouteTemplates),
+                next_route_id: 0,
+                registered_routes_only: false,
+                keeper_bounties: UnorderedMap::new(StorageKeys::KeeperBounties),
+                keeper_stats: UnorderedMap::new(StorageKeys::KeeperStats),
+                pending_bounty: None,
+                pool_white_list: UnorderedSet::new(StorageKeys::PoolWhiteList),
+                min_pool_liquidity: UnorderedMap::new(StorageKeys::MinPoolLiquidity),
+                // weights are set with set_recipients before the first distribute
+                recipients: legacy_recipients(
+                    [(burn_account_id, 0, 0), (company_account_id, 0, 0), (reward_account_id, 0, 0)]
+                ),
+                delivering_amount: 0,
+                total_burned: 0,
+                vestings: UnorderedMap::new(StorageKeys::Vestings),
+                total_vesting_amount: 0,
+                claimable_amounts: UnorderedMap::new(StorageKeys::ClaimableAmounts),
+                total_claimable_amount: 0,
+                claiming_amount: 0,
             })
         }
     }
@@ -126,14 +217,14 @@ impl Contract {
 impl Contract {
     fn data(&self) -> &ContractData {
         match &self.data {
-            VersionedContractData::V1000(data) => data,
+            VersionedContractData::V1001(data) => data,
             _ => unimplemented!(),
         }
     }
 
     fn data_mut(&mut self) -> &mut ContractData {
         match &mut self.data {
-            VersionedContractData::V1000(data) => data,
+            VersionedContractData::V1001(data) => data,
             _ => unimplemented!(),
         }
     }
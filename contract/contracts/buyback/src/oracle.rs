@@ -0,0 +1,226 @@
+use crate::*;
+use near_sdk::json_types::U64;
+use near_sdk::promise_result_as_success;
+
+pub const GAS_FOR_GET_PRICE_DATA: Gas = Gas(10 * Gas::ONE_TERA.0);
+pub const GAS_FOR_REFRESH_PRICES_CALLBACK: Gas = Gas(10 * Gas::ONE_TERA.0);
+
+/// USD value of one smallest unit of the asset is multiplier / 10^decimals.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct Price {
+    pub multiplier: U128,
+    pub decimals: u8,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct AssetOptionalPrice {
+    pub asset_id: String,
+    pub price: Option<Price>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct PriceData {
+    // unit: nano sec
+    pub timestamp: U64,
+    pub recency_duration_sec: u32,
+    pub prices: Vec<AssetOptionalPrice>,
+}
+
+#[ext_contract(ext_price_oracle)]
+pub trait PriceOracle {
+    fn get_price_data(&self, asset_ids: Option<Vec<String>>) -> PriceData;
+}
+
+/// A token price last read from the price oracle.
+#[derive(BorshDeserialize, BorshSerialize, Clone, PartialEq, Eq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct CachedPrice {
+    pub multiplier: u128,
+    pub decimals: u8,
+    // when the oracle reported the price. unit: sec
+    pub timestamp: u32,
+    // how long the oracle holds the price valid for. unit: sec
+    pub recency_duration: u32,
+}
+
+impl CachedPrice {
+    /// Neither older than max_price_age nor than the oracle holds the price valid for.
+    pub fn is_fresh(&self, current_time: u32, max_price_age: u32) -> bool {
+        current_time <= self.timestamp.saturating_add(std::cmp::min(max_price_age, self.recency_duration))
+    }
+}
+
+/// Fund token amount per buyback token amount implied by the oracle, scaled by PRICE_PRECISION.
+pub fn oracle_price(fund_price: &CachedPrice, buyback_price: &CachedPrice) -> Option<u128> {
+    // (m_b / 10^d_b) / (m_f / 10^d_f)
+    let num = U256::from(buyback_price.multiplier) * U256::from(10).pow(U256::from(fund_price.decimals)) * U256::from(PRICE_PRECISION);
+    let denom = U256::from(fund_price.multiplier) * U256::from(10).pow(U256::from(buyback_price.decimals));
+    if denom.is_zero() {
+        return None;
+    }
+    let price = num / denom;
+    if price > U256::from(u128::MAX) { None } else { Some(price.as_u128()) }
+}
+
+/// The buyback token amount worth `amount_in` of the fund token by the oracle.
+pub fn oracle_amount_out(amount_in: u128, fund_price: &CachedPrice, buyback_price: &CachedPrice) -> Option<u128> {
+    let num = U256::from(amount_in) * U256::from(fund_price.multiplier) * U256::from(10).pow(U256::from(buyback_price.decimals));
+    let denom = U256::from(buyback_price.multiplier) * U256::from(10).pow(U256::from(fund_price.decimals));
+    if denom.is_zero() {
+        return None;
+    }
+    let amount_out = num / denom;
+    if amount_out > U256::from(u128::MAX) { None } else { Some(amount_out.as_u128()) }
+}
+
+impl Contract {
+    /// The cached price of the token if it is not older than max_price_age.
+    pub fn internal_fresh_oracle_price(&self, token_id: &AccountId) -> Option<CachedPrice> {
+        let current_time = nano_to_sec(env::block_timestamp());
+        self.data().oracle_prices.get(token_id)
+            .filter(|price| price.is_fresh(current_time, self.data().max_price_age))
+    }
+
+    /// The fresh oracle prices of the fund token and the buyback token, None if no oracle is set.
+    /// Panics if an oracle is set but either price is missing or stale.
+    pub fn internal_oracle_prices(&self, fund_token_id: &AccountId) -> Option<(CachedPrice, CachedPrice)> {
+        self.data().price_oracle_id.as_ref()?;
+        let fund_price = self.internal_fresh_oracle_price(fund_token_id).expect(ERR131_STALE_ORACLE_PRICE);
+        let buyback_price = self.internal_fresh_oracle_price(&self.data().buyback_token_id).expect(ERR131_STALE_ORACLE_PRICE);
+        Some((fund_price, buyback_price))
+    }
+
+    /// Fund token per buyback token implied by the oracle when both prices are fresh.
+    pub fn internal_current_oracle_price(&self, fund_token_id: &AccountId) -> Option<u128> {
+        let fund_price = self.internal_fresh_oracle_price(fund_token_id)?;
+        let buyback_price = self.internal_fresh_oracle_price(&self.data().buyback_token_id)?;
+        oracle_price(&fund_price, &buyback_price)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Set the price oracle, None turns the oracle check of do_buyback off. Only can be called by owner.
+    #[payable]
+    pub fn set_price_oracle(&mut self, price_oracle_id: Option<AccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        // prices of the previous oracle are not comparable
+        self.data_mut().oracle_prices.clear();
+        self.data_mut().price_oracle_id = price_oracle_id;
+    }
+
+    /// Set how old a cached oracle price may be for do_buyback. Only can be called by owner.
+    #[payable]
+    pub fn set_max_price_age(&mut self, max_price_age: u32) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.data_mut().max_price_age = max_price_age;
+    }
+
+    /// Read the prices of the buyback token and the whitelisted tokens from the price oracle.
+    pub fn refresh_oracle_prices(&mut self) {
+        let price_oracle_id = self.data().price_oracle_id.clone().expect(ERR130_NO_PRICE_ORACLE);
+        let mut asset_ids: Vec<String> = self.data().token_white_list.iter().map(|token_id| token_id.to_string()).collect();
+        asset_ids.push(self.data().buyback_token_id.to_string());
+        ext_price_oracle::ext(price_oracle_id)
+            .with_static_gas(GAS_FOR_GET_PRICE_DATA)
+            .get_price_data(Some(asset_ids))
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_REFRESH_PRICES_CALLBACK)
+                    .callback_refresh_oracle_prices()
+            );
+    }
+
+    #[private]
+    pub fn callback_refresh_oracle_prices(&mut self) {
+        // unreadable price data keeps the cached prices
+        let price_data = promise_result_as_success()
+            .and_then(|cross_call_result| serde_json::from_slice::<PriceData>(&cross_call_result).ok());
+        let price_data = match price_data {
+            Some(price_data) => price_data,
+            None => {
+                log!("Failed to refresh oracle prices, {}", ERR102_CROSS_CONTRACT_FAILED);
+                return;
+            }
+        };
+        let timestamp = nano_to_sec(price_data.timestamp.0);
+        for AssetOptionalPrice { asset_id, price } in price_data.prices {
+            let (token_id, price) = match (asset_id.parse::<AccountId>(), price) {
+                (Ok(token_id), Some(price)) => (token_id, price),
+                _ => continue,
+            };
+            if token_id != self.data().buyback_token_id && !self.data().token_white_list.contains(&token_id) {
+                continue;
+            }
+            // never replace a price with an older one
+            if matches!(self.data().oracle_prices.get(&token_id), Some(cached) if cached.timestamp > timestamp) {
+                continue;
+            }
+            self.data_mut().oracle_prices.insert(&token_id, &CachedPrice {
+                multiplier: price.multiplier.0,
+                decimals: price.decimals,
+                timestamp,
+                recency_duration: price_data.recency_duration_sec,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::test::*;
+    use near_sdk::PromiseResult;
+
+    fn price(multiplier: u128, decimals: u8) -> CachedPrice {
+        CachedPrice { multiplier, decimals, timestamp: 1000, recency_duration: 900 }
+    }
+
+    #[test]
+    fn price_freshness() {
+        assert!(price(1, 0).is_fresh(1000, 0));
+        assert!(price(1, 0).is_fresh(1600, 600));
+        assert!(!price(1, 0).is_fresh(1601, 600));
+        // the oracle's recency duration is shorter than max_price_age
+        assert!(price(1, 0).is_fresh(1900, 1200));
+        assert!(!price(1, 0).is_fresh(1901, 1200));
+    }
+
+    #[test]
+    fn unreadable_price_data() {
+        let (mut context, mut contract) = setup_contract();
+        contract.set_price_oracle(Some(oracle_id()));
+        testing_env!(context.block_timestamp(sec_to_nano(1000)).build());
+        refresh_prices(&mut contract, &mut context, vec![(nusdt(), 10000, 10)]);
+        // prices the oracle holds valid for 90 sec are stale after it, whatever max_price_age allows
+        testing_env!(context.block_timestamp(sec_to_nano(1091)).build());
+        let cached = contract.get_oracle_price(nusdt()).unwrap();
+        assert_eq!((cached.timestamp, cached.fresh), (1000, false));
+
+        // the cached price is kept, and the callback doesn't panic
+        callback_with_result(&context, PromiseResult::Successful(b"{\"prices\": []}".to_vec()));
+        contract.callback_refresh_oracle_prices();
+        callback_with_result(&context, PromiseResult::Failed);
+        contract.callback_refresh_oracle_prices();
+        assert_eq!(contract.get_oracle_price(nusdt()).unwrap().multiplier.0, 10000);
+    }
+
+    #[test]
+    fn oracle_math() {
+        // 1 USDT with 6 decimals, 0.5 BRRR with 18 decimals
+        let usdt = price(10000, 10);
+        let brrr = price(5000, 22);
+        assert_eq!(oracle_price(&usdt, &brrr), Some(PRICE_PRECISION / 2 / 10u128.pow(12)));
+        assert_eq!(oracle_amount_out(10u128.pow(6), &usdt, &brrr), Some(2 * 10u128.pow(18)));
+        assert_eq!(oracle_price(&price(0, 10), &brrr), None);
+        assert_eq!(oracle_amount_out(10u128.pow(6), &usdt, &price(0, 22)), None);
+    }
+}
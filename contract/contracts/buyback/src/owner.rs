@@ -1,4 +1,5 @@
 use crate::*;
+use near_sdk::is_promise_success;
 
 impl Contract {
     pub fn assert_owner(&self) {
@@ -84,60 +85,107 @@ impl Contract {
                 )
                 
             );     
+            let current_time = nano_to_sec(env::block_timestamp());
+            if state == RunningState::Paused {
+                self.data_mut().paused_at = Some(current_time);
+            } else if let Some(paused_at) = self.data_mut().paused_at.take() {
+                self.internal_shift_rounds_for_pause(paused_at, current_time);
+            }
             self.data_mut().state = state;
         }
     }
 
+    /// Release the lock of a buyback or distribute whose callbacks never completed. Only can be called by owner.
+    /// The fund a stuck buyback locked is given back to its round, except spent_amount,
+    /// what the exchange did use of it, which is counted as spent.
     #[payable]
-    pub fn change_buyback_rate(&mut self, burn_rate: u32, company_rate: u32, reward_rate: u32) {
+    pub fn clear_pending_operation(&mut self, spent_amount: Option<U128>) {
         assert_one_yocto();
-        self.assert_owner_or_guardians();
-
-        assert!(burn_rate + company_rate + reward_rate == MAX_RATIO);
-        self.data_mut().burn_rate = burn_rate;
-        self.data_mut().company_rate = company_rate;
-        self.data_mut().reward_rate = reward_rate;
+        self.assert_owner();
+        if let Some(operation) = self.data_mut().pending_operation.take() {
+            log!("Pending {:?} operation cleared by {}", operation, env::predecessor_account_id());
+        }
+        if let Some(pending_buyback) = self.data_mut().pending_buyback.take() {
+            let spent_amount = spent_amount.map_or(0, |amount| amount.0);
+            require!(spent_amount <= pending_buyback.amount_in, ERR154_SPENT_EXCEEDS_LOCKED);
+            let mut round = self.internal_get_round(pending_buyback.round_id);
+            round.fund_locked -= pending_buyback.amount_in;
+            round.fund_cost += spent_amount;
+            self.internal_set_round(pending_buyback.round_id, &round);
+            self.internal_refund_spend(&round.fund_token_id, pending_buyback.spend_time, pending_buyback.amount_in - spent_amount);
+            self.internal_try_finish_active_round(&round.fund_token_id);
+            log!("Buyback on round {} released, {} of {} counted as spent", pending_buyback.round_id, spent_amount, pending_buyback.amount_in);
+        }
+        self.internal_release_pending_bounty();
     }
 
     #[payable]
-    pub fn change_burn_account_id(&mut self, burn_account_id: AccountId) {
+    pub fn change_buyback_token_id(&mut self, buyback_token_id: AccountId) {
         assert_one_yocto();
         self.assert_owner_or_guardians();
-        require!(self.data().belong_burn_amount == 0);
-        self.data_mut().burn_account_id = burn_account_id;
+        require!(self.internal_reserved_amount() == 0, ERR145_RECIPIENT_OWED);
+        self.data_mut().buyback_token_id = buyback_token_id;
     }
 
     #[payable]
-    pub fn change_company_account_id(&mut self, company_account_id: AccountId) {
+    pub fn set_max_slippage_bps(&mut self, max_slippage_bps: u32) {
         assert_one_yocto();
-        self.assert_owner_or_guardians();
-        require!(self.data().belong_company_amount == 0);
-        self.data_mut().company_account_id = company_account_id;
+        self.assert_owner();
+        require!(max_slippage_bps < MAX_RATIO, ERR128_INVALID_MAX_SLIPPAGE);
+        self.data_mut().max_slippage_bps = max_slippage_bps;
     }
 
     #[payable]
-    pub fn change_reward_account_id(&mut self, reward_account_id: AccountId) {
+    pub fn change_ref_exchange_id(&mut self, ref_exchange_id: AccountId) {
         assert_one_yocto();
         self.assert_owner_or_guardians();
-        require!(self.data().belong_reward_amount == 0);
-        self.data_mut().reward_account_id = reward_account_id;
+        self.data_mut().ref_exchange_id = ref_exchange_id;
     }
+}
 
+#[near_bindgen]
+impl Contract {
+    /// Cancel a queued round and send its unspent fund to receiver_id. Only can be called by owner.
     #[payable]
-    pub fn change_buyback_token_id(&mut self, buyback_token_id: AccountId) {
+    pub fn cancel_round(&mut self, round_id: u64, receiver_id: AccountId) {
         assert_one_yocto();
-        self.assert_owner_or_guardians();
-        require!(self.data().belong_burn_amount == 0 
-            && self.data().belong_company_amount == 0
-            && self.data().belong_reward_amount == 0);
-        self.data_mut().buyback_token_id = buyback_token_id;
+        self.assert_owner();
+        let mut round = self.internal_get_round(round_id);
+        require!(round.status == RoundStatus::Queued, ERR106_ROUND_NOT_QUEUED);
+        require!(round.fund_locked == 0, ERR107_ROUND_FUND_LOCKED);
+
+        let refund_amount = round.fund_amount - round.fund_cost;
+        let queue_index = self.internal_dequeue_round(&round.fund_token_id, round_id);
+        round.close(RoundStatus::Cancelled);
+        self.internal_set_round(round_id, &round);
+
+        ext_fungible_token::ext(round.fund_token_id)
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(
+                receiver_id.clone(),
+                U128(refund_amount),
+                None
+            ).then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_FT_TRANSFER_CALLBACK)
+                    .callback_cancel_round(round_id, receiver_id, U128(refund_amount), queue_index as u32)
+            );
     }
 
-    #[payable]
-    pub fn change_ref_exchange_id(&mut self, ref_exchange_id: AccountId) {
-        assert_one_yocto();
-        self.assert_owner_or_guardians();
-        self.data_mut().ref_exchange_id = ref_exchange_id;
+    #[private]
+    pub fn callback_cancel_round(&mut self, round_id: u64, receiver_id: AccountId, refund_amount: U128, queue_index: u32) {
+        let mut round = self.internal_get_round(round_id);
+        if is_promise_success() {
+            events::emit::cancel_round(round_id, &round.fund_token_id, &receiver_id, refund_amount.0);
+        } else {
+            // the refund didn't go through, put the round back to where it was
+            round.status = RoundStatus::Queued;
+            round.closed_at = None;
+            self.internal_set_round(round_id, &round);
+            self.internal_requeue_round(&round.fund_token_id, round_id, queue_index as usize);
+            log!("Failed to cancel buyback round {}, refund to {} failed", round_id, receiver_id);
+        }
     }
 }
 
@@ -155,7 +203,8 @@ impl Contract {
         let mut contract: Contract = env::state_read().expect(E003_NOT_INIT);
         // see if ContractData need upgrade
         contract.data = match contract.data {
-            VersionedContractData::V1000(data) => VersionedContractData::V1000(data),
+            VersionedContractData::V1000(data) => VersionedContractData::V1001(data.into()),
+            VersionedContractData::V1001(data) => VersionedContractData::V1001(data),
         };
         contract
     }
@@ -0,0 +1,207 @@
+use crate::*;
+use near_sdk::{Promise, PromiseResult};
+
+pub const GAS_FOR_GET_POOL: Gas = Gas(10 * Gas::ONE_TERA.0);
+pub const GAS_FOR_CHECK_POOLS_CALLBACK: Gas = Gas(10 * Gas::ONE_TERA.0);
+
+/// The part of the exchange's get_pool the liquidity check reads.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct RefPoolInfo {
+    pub token_account_ids: Vec<AccountId>,
+    pub amounts: Vec<U128>,
+}
+
+impl RefPoolInfo {
+    /// The amount of the token in the pool, 0 if the pool doesn't hold it.
+    pub fn reserve(&self, token_id: &AccountId) -> u128 {
+        self.token_account_ids.iter()
+            .position(|pool_token_id| pool_token_id == token_id)
+            .and_then(|index| self.amounts.get(index))
+            .map(|amount| amount.0)
+            .unwrap_or(0)
+    }
+}
+
+impl Contract {
+    /// An empty pool white list allows any pool.
+    pub fn internal_is_pool_allowed(&self, pool_id: u64) -> bool {
+        self.data().pool_white_list.is_empty() || self.data().pool_white_list.contains(&pool_id)
+    }
+
+    /// Whether a token swapped by the routes has a min pool liquidity.
+    pub fn internal_needs_pool_check(&self, routes: &[Vec<Action>]) -> bool {
+        routes.iter().flatten().any(|action| {
+            let Action::Swap(swap_action) = action;
+            self.data().min_pool_liquidity.get(&swap_action.token_in).is_some()
+                || self.data().min_pool_liquidity.get(&swap_action.token_out).is_some()
+        })
+    }
+
+    /// The first token of the swap the pool holds less of than its min pool liquidity, with the reserve and the min.
+    pub fn internal_shallow_pool_token<'a>(&self, swap_action: &'a SwapAction, pool_info: &RefPoolInfo) -> Option<(&'a AccountId, u128, u128)> {
+        [&swap_action.token_in, &swap_action.token_out].iter().find_map(|token_id| {
+            let min_pool_liquidity = self.data().min_pool_liquidity.get(token_id).unwrap_or(0);
+            let reserve = pool_info.reserve(token_id);
+            if reserve < min_pool_liquidity {
+                Some((*token_id, reserve, min_pool_liquidity))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// get_pool of every pool of the routes, joined so the callback reads them in order.
+    pub fn internal_get_pools(&self, routes: &[Vec<Action>]) -> Promise {
+        let mut get_pools: Option<Promise> = None;
+        for action in routes.iter().flatten() {
+            let Action::Swap(swap_action) = action;
+            let get_pool = ext_ref_exchange::ext(self.data().ref_exchange_id.clone())
+                .with_static_gas(GAS_FOR_GET_POOL)
+                .get_pool(swap_action.pool_id);
+            get_pools = Some(match get_pools {
+                Some(get_pools) => get_pools.and(get_pool),
+                None => get_pool,
+            });
+        }
+        get_pools.expect(ERR121_EMPTY_ROUTE)
+    }
+
+    /// Read every pool of the routes from the exchange, the callback checks their reserves and starts quoting.
+    pub fn internal_check_pools(&self, round_id: u64, amount_in: u128, spend_time: u32, swap_msgs: Vec<String>, routes: &[Vec<Action>]) {
+        let hops = routes.iter().flatten().count() as u64;
+        let check_pools_callback_gas = GAS_FOR_CHECK_POOLS_CALLBACK.0
+            + (GAS_FOR_GET_RETURN.0 + GAS_FOR_QUOTE_CALLBACK.0) * hops
+            + GAS_FOR_FT_BALANCE_OF.0
+            + start_buyback_gas(routes.len()).0;
+        self.internal_get_pools(routes).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(Gas(check_pools_callback_gas))
+                .callback_check_pools(round_id, U128(amount_in), spend_time, swap_msgs)
+        );
+    }
+
+    /// Read every pool of the simulated routes from the exchange, the callback notes the shallow ones and starts quoting.
+    pub fn internal_simulate_check_pools(&self, swap_msgs: Vec<String>, routes: &[Vec<Action>]) -> Promise {
+        let hops = routes.iter().flatten().count() as u64;
+        let simulate_check_pools_callback_gas = GAS_FOR_CHECK_POOLS_CALLBACK.0
+            + (GAS_FOR_GET_RETURN.0 + GAS_FOR_SIMULATE_CALLBACK.0) * hops;
+        self.internal_get_pools(routes).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(Gas(simulate_check_pools_callback_gas))
+                .callback_simulate_check_pools(swap_msgs)
+        )
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Extend the pools do_buyback may swap in, once it has any pool the others are rejected. Only can be called by owner.
+    #[payable]
+    pub fn extend_pool_white_list(&mut self, pool_ids: Vec<u64>) {
+        assert_one_yocto();
+        self.assert_owner();
+        for pool_id in pool_ids {
+            self.data_mut().pool_white_list.insert(&pool_id);
+        }
+    }
+
+    /// Remove pools from the pool white list. Only can be called by owner.
+    #[payable]
+    pub fn remove_pool_white_list(&mut self, pool_ids: Vec<u64>) {
+        assert_one_yocto();
+        self.assert_owner();
+        for pool_id in pool_ids {
+            let is_success = self.data_mut().pool_white_list.remove(&pool_id);
+            require!(is_success, ERR139_POOL_NOT_WHITELISTED);
+        }
+    }
+
+    /// Set how much of the token a pool must hold for do_buyback to swap the token in it, None removes the check. Only can be called by owner.
+    #[payable]
+    pub fn set_min_pool_liquidity(&mut self, token_id: AccountId, min_pool_liquidity: Option<U128>) {
+        assert_one_yocto();
+        self.assert_owner();
+        match min_pool_liquidity {
+            Some(min_pool_liquidity) => self.data_mut().min_pool_liquidity.insert(&token_id, &min_pool_liquidity.0),
+            None => self.data_mut().min_pool_liquidity.remove(&token_id),
+        };
+    }
+
+    #[private]
+    pub fn callback_check_pools(&mut self, round_id: u64, amount_in: U128, spend_time: u32, swap_msgs: Vec<String>) {
+        let routes: Vec<Vec<Action>> = swap_msgs.iter().map(|swap_msg| swap_actions(swap_msg)).collect();
+        for (index, action) in routes.iter().flatten().enumerate() {
+            let Action::Swap(swap_action) = action;
+            let pool_info = match env::promise_result(index as u64) {
+                PromiseResult::Successful(cross_call_result) => serde_json::from_slice::<RefPoolInfo>(&cross_call_result).ok(),
+                _ => None,
+            };
+            let pool_info = match pool_info {
+                Some(pool_info) => pool_info,
+                None => {
+                    log!("Buyback on round {} aborted, {}", round_id, ERR102_CROSS_CONTRACT_FAILED);
+                    self.internal_abort_buyback(round_id, amount_in.0, spend_time);
+                    return;
+                }
+            };
+            if let Some((token_id, reserve, min_pool_liquidity)) = self.internal_shallow_pool_token(swap_action, &pool_info) {
+                log!("Buyback on round {} aborted, {}, pool {} holds {} {} < {}", round_id, ERR140_POOL_LIQUIDITY_TOO_LOW, swap_action.pool_id, reserve, token_id, min_pool_liquidity);
+                self.internal_abort_buyback(round_id, amount_in.0, spend_time);
+                return;
+            }
+        }
+        let Action::Swap(first_swap) = &routes[0][0];
+        let route_amount_in = first_swap.amount_in.expect(ERR100_WRONG_MSG_FORMAT).0;
+        self.internal_quote_hop(round_id, amount_in.0, spend_time, swap_msgs, 0, route_amount_in, 0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::test::*;
+    use near_sdk::{RuntimeFeesConfig, VMConfig};
+    use std::collections::HashMap;
+
+    #[test]
+    #[should_panic(expected = "E139")]
+    fn pool_not_whitelisted() {
+        let (mut context, mut contract) = setup_contract();
+        contract.extend_pool_white_list(vec![1, 2]);
+        assert_eq!(contract.get_metadata().pool_white_list, vec![1, 2]);
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).build());
+        start_buyback(&mut contract, &mut context, nusdt(), d(10, 6));
+    }
+
+    #[test]
+    fn min_pool_liquidity() {
+        let (mut context, mut contract) = setup_contract();
+        contract.set_min_pool_liquidity(nusdt(), Some(U128(d(10000, 6))));
+        assert_eq!(contract.get_metadata().min_pool_liquidity.get(&nusdt()), Some(&U128(d(10000, 6))));
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+
+        // a shallow pool aborts the buyback before quoting
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(owner_id()).build());
+        contract.do_buyback(swap_msg(nusdt(), d(10, 6)), None);
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(90, 6));
+        context.predecessor_account_id(contract_account_id());
+        testing_env!(context.build(), VMConfig::test(), RuntimeFeesConfig::test(), HashMap::default(),
+            vec![pool_result(vec![(nusdt(), d(5000, 6)), (buyback_token_id(), d(5000, 18))])]);
+        contract.callback_check_pools(0, U128(d(10, 6)), 1100, vec![swap_msg(nusdt(), d(10, 6))]);
+        assert_eq!(contract.get_metadata().pending_operation, None);
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(100, 6));
+
+        // a deep one goes on to the quote
+        testing_env!(context.predecessor_account_id(owner_id()).build());
+        contract.do_buyback(swap_msg(nusdt(), d(10, 6)), None);
+        context.predecessor_account_id(contract_account_id());
+        testing_env!(context.build(), VMConfig::test(), RuntimeFeesConfig::test(), HashMap::default(),
+            vec![pool_result(vec![(nusdt(), d(20000, 6)), (buyback_token_id(), d(20000, 18))])]);
+        contract.callback_check_pools(0, U128(d(10, 6)), 1100, vec![swap_msg(nusdt(), d(10, 6))]);
+        assert_eq!(contract.get_metadata().pending_operation, Some(PendingOperation::Buyback));
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(90, 6));
+    }
+}
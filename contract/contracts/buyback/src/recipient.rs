@@ -0,0 +1,513 @@
+use crate::*;
+use near_sdk::{is_promise_success, promise_result_as_success};
+
+pub const GAS_FOR_CLAIM_CALLBACK: Gas = Gas(10 * Gas::ONE_TERA.0);
+
+pub const MAX_RECIPIENTS: usize = 10;
+// gas distribute may spend handing out the shares of all recipients
+pub const MAX_DELIVER_SHARES_GAS: Gas = Gas(200 * Gas::ONE_TERA.0);
+// leaves the receiver room for its ft_on_transfer
+pub const GAS_FOR_DELIVER_TRANSFER_CALL: Gas = Gas(40 * Gas::ONE_TERA.0);
+pub const GAS_FOR_DELIVER_TRANSFER_CALL_CALLBACK: Gas = Gas(5 * Gas::ONE_TERA.0);
+pub const GAS_FOR_BURN: Gas = Gas(10 * Gas::ONE_TERA.0);
+pub const GAS_FOR_BURN_CALLBACK: Gas = Gas(5 * Gas::ONE_TERA.0);
+
+#[ext_contract(ext_burnable_token)]
+pub trait BurnableToken {
+    fn burn(&mut self, account_id: AccountId, amount: U128);
+}
+
+/// How distribute hands a recipient its share of the buyback token.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum DeliveryMode {
+    // ft_transfer to the recipient account
+    Transfer,
+    // burn from the contract's balance with the buyback token's burn, the recipient account only names the share
+    Burn,
+    // ft_transfer_call to the recipient contract with msg, e.g. a farm's deposit reward message
+    TransferCall { msg: String },
+    // locked for the recipient account, which claims it with claim_vested as it vests linearly over duration. unit: sec
+    Vesting { duration: u32 },
+    // credited to the recipient account, which withdraws it with claim
+    Claim,
+}
+
+impl DeliveryMode {
+    /// Gas the callback of distribute needs to hand out a share this way.
+    pub fn gas(&self) -> Gas {
+        match self {
+            DeliveryMode::Transfer => Gas(GAS_FOR_FT_TRANSFER.0 + GAS_FOR_FT_TRANSFER_CALLBACK.0),
+            DeliveryMode::Burn => Gas(GAS_FOR_BURN.0 + GAS_FOR_BURN_CALLBACK.0),
+            DeliveryMode::TransferCall { .. } => Gas(GAS_FOR_DELIVER_TRANSFER_CALL.0 + GAS_FOR_DELIVER_TRANSFER_CALL_CALLBACK.0),
+            DeliveryMode::Vesting { .. } | DeliveryMode::Claim => Gas(0),
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct RecipientConfig {
+    pub account_id: AccountId,
+    // share of each distribute, in bps
+    pub weight_bps: u32,
+    pub label: String,
+    pub delivery_mode: DeliveryMode,
+}
+
+/// A share of the buyback token that distribute hands out.
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct Recipient {
+    pub account_id: AccountId,
+    pub weight_bps: u32,
+    pub label: String,
+    pub delivery_mode: DeliveryMode,
+    // part of the share that failed to be delivered, sent along with the next distribute
+    pub owed_amount: u128,
+}
+
+impl Recipient {
+    pub fn new(config: RecipientConfig, owed_amount: u128) -> Self {
+        Self {
+            account_id: config.account_id,
+            weight_bps: config.weight_bps,
+            label: config.label,
+            delivery_mode: config.delivery_mode,
+            owed_amount,
+        }
+    }
+}
+
+impl Contract {
+    pub fn internal_total_owed_amount(&self) -> u128 {
+        self.data().recipients.iter().map(|recipient| recipient.owed_amount).sum()
+    }
+
+    /// Buyback token held for recipients, which distribute must not hand out again.
+    pub fn internal_reserved_amount(&self) -> u128 {
+        self.internal_total_owed_amount()
+            + self.data().delivering_amount
+            + self.data().total_vesting_amount
+            + self.data().total_claimable_amount
+    }
+
+    pub fn internal_add_claimable_amount(&mut self, account_id: &AccountId, amount: u128) {
+        let claimable_amount = self.data().claimable_amounts.get(account_id).unwrap_or(0);
+        self.data_mut().claimable_amounts.insert(account_id, &(claimable_amount + amount));
+    }
+
+    pub fn internal_recipient_weights_valid(&self) -> bool {
+        self.data().recipients.iter().map(|recipient| recipient.weight_bps).sum::<u32>() == MAX_RATIO
+    }
+
+    /// Put amount back into what the recipient is owed, or leave it to the next distribute if it was removed since.
+    pub fn internal_add_owed_amount(&mut self, account_id: &AccountId, amount: u128) {
+        match self.data_mut().recipients.iter_mut().find(|recipient| recipient.account_id == *account_id) {
+            Some(recipient) => recipient.owed_amount += amount,
+            None => log!("Recipient {} removed, {} left for the next distribute", account_id, amount),
+        }
+    }
+
+    /// Stop reserving a share sent out by distribute once its delivery resolved.
+    pub fn internal_resolve_delivery(&mut self, amount: u128) {
+        // shares sent before delivering_amount was tracked resolve without having been counted
+        self.data_mut().delivering_amount = self.data().delivering_amount.saturating_sub(amount);
+    }
+
+    /// Send the recipient amount of the buyback token the way its delivery mode says.
+    /// A share sent out stays reserved until its callback resolves it.
+    pub fn internal_deliver_share(&mut self, recipient: &Recipient, amount: u128) {
+        if recipient.delivery_mode.gas().0 > 0 {
+            self.data_mut().delivering_amount += amount;
+        }
+        match &recipient.delivery_mode {
+            DeliveryMode::Transfer => {
+                ext_fungible_token::ext(self.data().buyback_token_id.clone())
+                    .with_attached_deposit(1)
+                    .with_static_gas(GAS_FOR_FT_TRANSFER)
+                    .ft_transfer(
+                        recipient.account_id.clone(),
+                        U128(amount),
+                        None
+                    ).then(
+                        Self::ext(env::current_account_id())
+                            .with_static_gas(GAS_FOR_FT_TRANSFER_CALLBACK)
+                            .callback_ft_transfer(recipient.account_id.clone(), U128(amount))
+                    );
+            }
+            DeliveryMode::Burn => {
+                ext_burnable_token::ext(self.data().buyback_token_id.clone())
+                    .with_static_gas(GAS_FOR_BURN)
+                    .burn(
+                        env::current_account_id(),
+                        U128(amount)
+                    ).then(
+                        Self::ext(env::current_account_id())
+                            .with_static_gas(GAS_FOR_BURN_CALLBACK)
+                            .callback_burn(recipient.account_id.clone(), U128(amount))
+                    );
+            }
+            DeliveryMode::TransferCall { msg } => {
+                ext_fungible_token::ext(self.data().buyback_token_id.clone())
+                    .with_attached_deposit(1)
+                    .with_static_gas(GAS_FOR_DELIVER_TRANSFER_CALL)
+                    .ft_transfer_call(
+                        recipient.account_id.clone(),
+                        U128(amount),
+                        None,
+                        msg.clone()
+                    ).then(
+                        Self::ext(env::current_account_id())
+                            .with_static_gas(GAS_FOR_DELIVER_TRANSFER_CALL_CALLBACK)
+                            .callback_deliver_transfer_call(recipient.account_id.clone(), U128(amount))
+                    );
+            }
+            DeliveryMode::Vesting { duration } => self.internal_add_vesting(&recipient.account_id, amount, *duration),
+            DeliveryMode::Claim => {
+                self.internal_add_claimable_amount(&recipient.account_id, amount);
+                self.data_mut().total_claimable_amount += amount;
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Replace the recipients of distribute, their weights must add up to 10000 bps.
+    /// A recipient that is still owed a share can't be dropped, and keeps what it is owed.
+    #[payable]
+    pub fn set_recipients(&mut self, recipients: Vec<RecipientConfig>) {
+        assert_one_yocto();
+        self.assert_owner_or_guardians();
+        require!(!recipients.is_empty() && recipients.len() <= MAX_RECIPIENTS, ERR144_INVALID_RECIPIENTS);
+        require!(recipients.iter().map(|config| config.weight_bps).sum::<u32>() == MAX_RATIO, ERR143_INVALID_RECIPIENT_WEIGHTS);
+        for (index, config) in recipients.iter().enumerate() {
+            require!(recipients[..index].iter().all(|other| other.account_id != config.account_id), ERR144_INVALID_RECIPIENTS);
+        }
+        require!(recipients.iter().map(|config| config.delivery_mode.gas().0).sum::<u64>() <= MAX_DELIVER_SHARES_GAS.0, ERR146_RECIPIENTS_GAS_EXCEEDED);
+        for recipient in self.data().recipients.iter() {
+            require!(recipient.owed_amount == 0 || recipients.iter().any(|config| config.account_id == recipient.account_id), ERR145_RECIPIENT_OWED);
+        }
+        let recipients = recipients.into_iter().map(|config| {
+            let owed_amount = self.data().recipients.iter()
+                .find(|recipient| recipient.account_id == config.account_id)
+                .map(|recipient| recipient.owed_amount)
+                .unwrap_or(0);
+            Recipient::new(config, owed_amount)
+        }).collect();
+        self.data_mut().recipients = recipients;
+    }
+
+    #[private]
+    pub fn callback_burn(&mut self, account_id: AccountId, amount: U128) {
+        self.internal_resolve_delivery(amount.0);
+        if is_promise_success() {
+            self.data_mut().total_burned += amount.0;
+            events::emit::burn(&account_id, &self.data().buyback_token_id, amount.0, self.data().total_burned);
+        } else {
+            log!("Failed to burn {} of the {} share", amount.0, account_id);
+            self.internal_add_owed_amount(&account_id, amount.0);
+        }
+    }
+
+    /// The token resolves ft_transfer_call to the amount the receiver kept, the refunded rest stays owed to it.
+    #[private]
+    pub fn callback_deliver_transfer_call(&mut self, account_id: AccountId, amount: U128) {
+        self.internal_resolve_delivery(amount.0);
+        let used_amount = promise_result_as_success()
+            .and_then(|cross_call_result| serde_json::from_slice::<U128>(&cross_call_result).ok())
+            .map(|used_amount| used_amount.0)
+            .unwrap_or(0);
+        let refund_amount = amount.0.saturating_sub(used_amount);
+        if refund_amount > 0 {
+            log!("{} of the {} share refunded by ft_transfer_call", refund_amount, account_id);
+            self.internal_add_owed_amount(&account_id, refund_amount);
+        }
+    }
+
+    /// Withdraw what distribute credited to the caller, which is credited back if the transfer fails.
+    pub fn claim(&mut self) {
+        self.assert_no_buyback_pending();
+        let account_id = env::predecessor_account_id();
+        let amount = self.data_mut().claimable_amounts.remove(&account_id).unwrap_or(0);
+        require!(amount > 0, ERR149_NOTHING_TO_CLAIM);
+        // the claim stays in the balance and reserved from distribute until the transfer resolves
+        self.data_mut().claiming_amount += amount;
+        ext_fungible_token::ext(self.data().buyback_token_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(
+                account_id.clone(),
+                U128(amount),
+                None
+            ).then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_CLAIM_CALLBACK)
+                    .callback_claim(account_id, U128(amount))
+            );
+    }
+
+    #[private]
+    pub fn callback_claim(&mut self, account_id: AccountId, amount: U128) {
+        self.data_mut().claiming_amount -= amount.0;
+        if is_promise_success() {
+            self.data_mut().total_claimable_amount -= amount.0;
+        } else {
+            log!("Failed to send claim {} to {}", amount.0, account_id);
+            self.internal_add_claimable_amount(&account_id, amount.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::test::*;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::PromiseResult;
+
+    pub fn ecosystem_account_id() -> AccountId {
+        AccountId::new_unchecked("ecosystem".to_string())
+    }
+
+    #[test]
+    fn weighted_recipients() {
+        let (mut context, mut contract) = setup_contract();
+        assert!(contract.get_recipients().iter().all(|recipient| recipient.weight_bps == 0));
+        contract.set_recipients(recipient_configs(vec![
+            (burn_account_id(), 2000), (company_account_id(), 2000), (reward_account_id(), 5000), (ecosystem_account_id(), 1000),
+        ]));
+        testing_env!(context.attached_deposit(0).build());
+        contract.distribute();
+
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(100, 18))).unwrap()));
+        contract.callback_to_distribute();
+        assert!(contract.get_metadata().pending_operation.is_none());
+        // the ecosystem transfer failed, it is owed its share until the next distribute
+        callback_with_result(&context, PromiseResult::Failed);
+        contract.callback_ft_transfer(ecosystem_account_id(), U128(d(10, 18)));
+        let owed_amounts: Vec<Balance> = contract.get_recipients().iter().map(|recipient| recipient.owed_amount.0).collect();
+        assert_eq!(owed_amounts, vec![0, 0, 0, d(10, 18)]);
+        // the other shares stay reserved until their transfers resolve
+        assert_eq!(contract.get_metadata().delivering_amount.0, d(90, 18));
+        callback_with_result(&context, PromiseResult::Successful(vec![]));
+        contract.callback_ft_transfer(burn_account_id(), U128(d(20, 18)));
+        contract.callback_ft_transfer(company_account_id(), U128(d(20, 18)));
+        contract.callback_ft_transfer(reward_account_id(), U128(d(50, 18)));
+        assert_eq!(contract.get_metadata().delivering_amount.0, 0);
+
+        // a recipient kept by set_recipients keeps what it is owed
+        testing_env!(context.predecessor_account_id(owner_id()).attached_deposit(1).build());
+        contract.set_recipients(recipient_configs(vec![(burn_account_id(), 5000), (ecosystem_account_id(), 5000)]));
+        assert_eq!(contract.get_recipients()[1].owed_amount.0, d(10, 18));
+        testing_env!(context.attached_deposit(0).build());
+        contract.distribute();
+        // 20 new on top of the 10 owed
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(30, 18))).unwrap()));
+        contract.callback_to_distribute();
+        assert_eq!(contract.get_recipients()[1].owed_amount.0, 0);
+    }
+
+    #[test]
+    fn distribute_in_flight() {
+        let (mut context, mut contract) = setup_contract();
+        contract.set_recipients(recipient_configs(vec![(company_account_id(), 4000), (reward_account_id(), 6000)]));
+        testing_env!(context.attached_deposit(0).build());
+        contract.distribute();
+        // a malformed balance aborts distribute and still releases the lock
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(b"nan".to_vec()));
+        contract.callback_to_distribute();
+        assert!(contract.get_metadata().pending_operation.is_none());
+
+        testing_env!(context.predecessor_account_id(owner_id()).build());
+        contract.distribute();
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(100, 18))).unwrap()));
+        contract.callback_to_distribute();
+        // the transfers haven't resolved and the tokens are still in the balance, they aren't handed out again
+        testing_env!(context.predecessor_account_id(owner_id()).build());
+        contract.distribute();
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(100, 18))).unwrap()));
+        contract.callback_to_distribute();
+        assert_eq!(contract.get_metadata().delivering_amount.0, d(100, 18));
+        // a failed transfer moves its share from delivering to owed
+        callback_with_result(&context, PromiseResult::Failed);
+        contract.callback_ft_transfer(company_account_id(), U128(d(40, 18)));
+        assert_eq!(contract.get_metadata().delivering_amount.0, d(60, 18));
+        assert_eq!(contract.get_recipients()[0].owed_amount.0, d(40, 18));
+    }
+
+    #[test]
+    #[should_panic(expected = "E143")]
+    fn recipient_weights_must_add_up() {
+        let (_, mut contract) = setup_contract();
+        contract.set_recipients(recipient_configs(vec![(burn_account_id(), 2000), (reward_account_id(), 6000)]));
+    }
+
+    #[test]
+    #[should_panic(expected = "E145")]
+    fn owed_recipient_kept() {
+        let (mut context, mut contract) = setup_contract();
+        testing_env!(context.predecessor_account_id(contract_account_id()).build());
+        callback_with_result(&context, PromiseResult::Failed);
+        contract.callback_ft_transfer(company_account_id(), U128(d(10, 18)));
+        testing_env!(context.predecessor_account_id(owner_id()).attached_deposit(1).build());
+        contract.set_recipients(recipient_configs(vec![(burn_account_id(), 5000), (reward_account_id(), 5000)]));
+    }
+
+    #[test]
+    fn burn_recipient() {
+        let (mut context, mut contract) = setup_contract();
+        let mut recipients = recipient_configs(vec![(burn_account_id(), 2000), (reward_account_id(), 8000)]);
+        recipients[0].delivery_mode = DeliveryMode::Burn;
+        contract.set_recipients(recipients);
+        testing_env!(context.attached_deposit(0).build());
+        contract.distribute();
+
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(100, 18))).unwrap()));
+        contract.callback_to_distribute();
+        callback_with_result(&context, PromiseResult::Successful(vec![]));
+        contract.callback_burn(burn_account_id(), U128(d(20, 18)));
+        assert_eq!(contract.get_total_burned().0, d(20, 18));
+        // a failed burn is owed to the burn share, not counted as burned
+        callback_with_result(&context, PromiseResult::Failed);
+        contract.callback_burn(burn_account_id(), U128(d(5, 18)));
+        assert_eq!(contract.get_total_burned().0, d(20, 18));
+        assert_eq!(contract.get_recipients()[0].owed_amount.0, d(5, 18));
+    }
+
+    #[test]
+    fn transfer_call_recipient() {
+        let (mut context, mut contract) = setup_contract();
+        let mut recipients = recipient_configs(vec![(company_account_id(), 4000), (reward_account_id(), 6000)]);
+        recipients[1].delivery_mode = DeliveryMode::TransferCall { msg: "\"DepositReward\"".to_string() };
+        contract.set_recipients(recipients);
+        testing_env!(context.attached_deposit(0).build());
+        contract.distribute();
+
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(100, 18))).unwrap()));
+        contract.callback_to_distribute();
+        // the farm kept 50 of the 60, the refunded 10 is owed to it
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(50, 18))).unwrap()));
+        contract.callback_deliver_transfer_call(reward_account_id(), U128(d(60, 18)));
+        assert_eq!(contract.get_recipients()[1].owed_amount.0, d(10, 18));
+        callback_with_result(&context, PromiseResult::Failed);
+        contract.callback_deliver_transfer_call(reward_account_id(), U128(d(60, 18)));
+        assert_eq!(contract.get_recipients()[1].owed_amount.0, d(70, 18));
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(60, 18))).unwrap()));
+        contract.callback_deliver_transfer_call(reward_account_id(), U128(d(60, 18)));
+        assert_eq!(contract.get_recipients()[1].owed_amount.0, d(70, 18));
+    }
+
+    #[test]
+    #[should_panic(expected = "E146")]
+    fn recipients_gas_exceeded() {
+        let (_, mut contract) = setup_contract();
+        let recipients = (0..10).map(|index| RecipientConfig {
+            account_id: AccountId::new_unchecked(format!("farm{}", index)),
+            weight_bps: 1000,
+            label: format!("farm{}", index),
+            delivery_mode: DeliveryMode::TransferCall { msg: "".to_string() },
+        }).collect();
+        contract.set_recipients(recipients);
+    }
+
+    #[test]
+    fn claim_recipient() {
+        let (mut context, mut contract) = setup_contract();
+        let mut recipients = recipient_configs(vec![(company_account_id(), 4000), (reward_account_id(), 6000)]);
+        recipients[0].delivery_mode = DeliveryMode::Claim;
+        contract.set_recipients(recipients);
+        testing_env!(context.attached_deposit(0).build());
+        contract.distribute();
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(100, 18))).unwrap()));
+        contract.callback_to_distribute();
+        assert_eq!(contract.get_claimable_amount(company_account_id()).0, d(40, 18));
+        // the 60 sent to reward stays reserved until its transfer resolves
+        assert_eq!(contract.get_metadata().delivering_amount.0, d(60, 18));
+        callback_with_result(&context, PromiseResult::Successful(vec![]));
+        contract.callback_ft_transfer(reward_account_id(), U128(d(60, 18)));
+        assert_eq!(contract.get_metadata().delivering_amount.0, 0);
+
+        // the credited 40 isn't distributed again
+        testing_env!(context.predecessor_account_id(owner_id()).build());
+        contract.distribute();
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(90, 18))).unwrap()));
+        contract.callback_to_distribute();
+        let claimable_amounts = contract.list_claimable_amounts(None, None);
+        assert_eq!(claimable_amounts.len(), 1);
+        assert_eq!(claimable_amounts[0].amount.0, d(60, 18));
+        callback_with_result(&context, PromiseResult::Successful(vec![]));
+        contract.callback_ft_transfer(reward_account_id(), U128(d(30, 18)));
+
+        // the claim in flight is still in the balance and isn't distributed while it is sent
+        testing_env!(context.predecessor_account_id(company_account_id()).build());
+        contract.claim();
+        assert_eq!(contract.get_claimable_amount(company_account_id()).0, 0);
+        assert_eq!(contract.get_metadata().total_claimable_amount.0, d(60, 18));
+        testing_env!(context.predecessor_account_id(owner_id()).build());
+        contract.distribute();
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(60, 18))).unwrap()));
+        contract.callback_to_distribute();
+        assert_eq!(contract.get_metadata().delivering_amount.0, 0);
+        // a failed claim is credited back, and the balance still covers it
+        callback_with_result(&context, PromiseResult::Failed);
+        contract.callback_claim(company_account_id(), U128(d(60, 18)));
+        assert_eq!(contract.get_claimable_amount(company_account_id()).0, d(60, 18));
+        assert_eq!(contract.get_metadata().total_claimable_amount.0, d(60, 18));
+        testing_env!(context.predecessor_account_id(company_account_id()).build());
+        contract.claim();
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(vec![]));
+        contract.callback_claim(company_account_id(), U128(d(60, 18)));
+        assert_eq!(contract.get_metadata().total_claimable_amount.0, 0);
+        assert!(contract.list_claimable_amounts(None, None).is_empty());
+    }
+
+    /// A claim recipient credited all of a distribute of 100, with a round to buy back from.
+    fn setup_claim(context: &mut VMContextBuilder, contract: &mut Contract) {
+        let mut recipients = recipient_configs(vec![(company_account_id(), 10000)]);
+        recipients[0].delivery_mode = DeliveryMode::Claim;
+        contract.set_recipients(recipients);
+        fund_round(contract, context, buyback_info(1000, 100, 10));
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(owner_id()).build());
+        contract.distribute();
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(context, PromiseResult::Successful(serde_json::to_vec(&U128(d(100, 18))).unwrap()));
+        contract.callback_to_distribute();
+    }
+
+    #[test]
+    #[should_panic(expected = "E119")]
+    fn claim_during_buyback() {
+        let (mut context, mut contract) = setup_contract();
+        setup_claim(&mut context, &mut contract);
+        // the buyback measures what it received by the balance, which the claim would lower
+        start_buyback(&mut contract, &mut context, nusdt(), d(10, 6));
+        testing_env!(context.predecessor_account_id(company_account_id()).build());
+        contract.claim();
+    }
+
+    #[test]
+    #[should_panic(expected = "E152")]
+    fn buyback_during_claim() {
+        let (mut context, mut contract) = setup_contract();
+        setup_claim(&mut context, &mut contract);
+        testing_env!(context.predecessor_account_id(company_account_id()).build());
+        contract.claim();
+        assert_eq!(contract.get_metadata().claiming_amount.0, d(100, 18));
+        start_buyback(&mut contract, &mut context, nusdt(), d(10, 6));
+    }
+}
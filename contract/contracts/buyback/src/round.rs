@@ -0,0 +1,302 @@
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Eq, PartialEq, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum RoundStatus {
+    Queued, Finished, Cancelled
+}
+
+/// How a top-up is merged into a running round.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum TopUpMode {
+    /// Release the unreleased fund plus the top-up by the current end time.
+    KeepEndTime,
+    /// Keep the current release rate and push the end time back.
+    ExtendEndTime,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct Round {
+    pub fund_token_id: AccountId,
+    pub fund_amount: u128,
+    pub fund_cost: u128,
+    // amount_in of buybacks sent to the exchange and waiting for callback
+    pub fund_locked: u128,
+    // already released when the schedule starts, non-zero once the round is topped up
+    pub released_at_start: u128,
+    // unit: sec
+    pub start_time: u32,
+    // unit: sec
+    pub total_buyback_time: u32,
+    // unit: sec
+    pub buyback_internal: u32,
+    pub release_curve: ReleaseCurve,
+    pub status: RoundStatus,
+    // how long the contract has been paused during the schedule, already added to start_time. unit: sec
+    pub paused_duration: u32,
+    // take over the unspent fund of the rounds before it once they have ended
+    pub roll_over_remainder: bool,
+    // unspent fund taken over from the rounds before it
+    pub rolled_in_amount: u128,
+    // unspent fund handed over to the round after it
+    pub rolled_over_amount: u128,
+    // buyback token received from the exchange
+    pub received_amount: u128,
+    // number of do_buyback that spent some of the fund
+    pub executions: u32,
+    // when the round was finished or cancelled. unit: sec
+    pub closed_at: Option<u32>,
+    // max fund spent per buyback token of a do_buyback, scaled by PRICE_PRECISION
+    pub max_price: Option<u128>,
+    // price implied by the last exchange quote of a do_buyback, scaled by PRICE_PRECISION
+    pub last_quote_price: Option<u128>,
+    // unit: sec
+    pub last_quote_time: Option<u32>,
+}
+
+impl Round {
+    pub fn new(fund_token_id: AccountId, fund_amount: u128, start_time: u32, total_buyback_time: u32, buyback_internal: u32, release_curve: ReleaseCurve) -> Self {
+        Self {
+            fund_token_id,
+            fund_amount,
+            fund_cost: 0,
+            fund_locked: 0,
+            released_at_start: 0,
+            start_time,
+            total_buyback_time,
+            buyback_internal,
+            release_curve,
+            status: RoundStatus::Queued,
+            paused_duration: 0,
+            roll_over_remainder: false,
+            rolled_in_amount: 0,
+            rolled_over_amount: 0,
+            received_amount: 0,
+            executions: 0,
+            closed_at: None,
+            max_price: None,
+            last_quote_price: None,
+            last_quote_time: None,
+        }
+    }
+
+    pub fn end_time(&self) -> u32 {
+        self.start_time.saturating_add(self.total_buyback_time)
+    }
+
+    /// Average fund spent per buyback token, scaled by PRICE_PRECISION.
+    pub fn average_price(&self) -> Option<u128> {
+        if self.received_amount > 0 {
+            Some(u128_ratio(self.fund_cost, PRICE_PRECISION, self.received_amount))
+        } else {
+            None
+        }
+    }
+
+    /// Whether spending `amount_in` for `amount_out` pays more per buyback token than max_price.
+    pub fn exceeds_max_price(&self, amount_in: u128, amount_out: u128) -> bool {
+        match self.max_price {
+            Some(max_price) => amount_out == 0 || u128_ratio(amount_in, PRICE_PRECISION, amount_out) > max_price,
+            None => false,
+        }
+    }
+
+    pub fn close(&mut self, status: RoundStatus) {
+        self.status = status;
+        self.closed_at = Some(nano_to_sec(env::block_timestamp()));
+    }
+
+    pub fn is_fully_spent(&self) -> bool {
+        self.fund_cost == self.fund_amount && self.fund_locked == 0
+    }
+
+    /// The amount that has been released by the schedule at `current_time`.
+    pub fn released_amount(&self, current_time: u32) -> u128 {
+        if current_time <= self.end_time() {
+            let pass_time = current_time.saturating_sub(self.start_time);
+            self.released_at_start + self.release_curve.released_amount(
+                self.fund_amount.saturating_sub(self.released_at_start),
+                pass_time,
+                self.total_buyback_time,
+                self.buyback_internal
+            )
+        } else {
+            self.fund_amount
+        }
+    }
+
+    /// The amount that has been released by the schedule at `current_time`
+    /// and is neither spent nor locked in an unfinished buyback.
+    pub fn available_fund_amount(&self, current_time: u32) -> u128 {
+        self.released_amount(current_time)
+            .checked_sub(self.fund_cost)
+            .and_then(|amount| amount.checked_sub(self.fund_locked))
+            .unwrap_or(0)
+    }
+
+    /// Push the schedule back by the part of the pause [paused_at, resumed_at) that falls into it,
+    /// so the fund is released at the original rate after resume.
+    pub fn shift_for_pause(&mut self, paused_at: u32, resumed_at: u32) {
+        if paused_at >= self.end_time() {
+            return;
+        }
+        let shift = resumed_at.saturating_sub(std::cmp::max(paused_at, self.start_time));
+        self.start_time = self.start_time.saturating_add(shift);
+        self.paused_duration = self.paused_duration.saturating_add(shift);
+    }
+
+    /// Add the unspent fund of an ended round, released over what is left of the schedule.
+    pub fn roll_in(&mut self, amount: u128, current_time: u32) {
+        if current_time < self.end_time() {
+            self.top_up(amount, &TopUpMode::KeepEndTime, current_time);
+        } else {
+            self.fund_amount += amount;
+        }
+        self.rolled_in_amount += amount;
+    }
+
+    /// Add `amount` to the round, restarting the release curve from now (from the
+    /// current interval for stepwise curves) so that what has been released so far stays unchanged.
+    pub fn top_up(&mut self, amount: u128, mode: &TopUpMode, current_time: u32) {
+        require!(current_time < self.end_time(), ERR108_ROUND_SCHEDULE_ENDED);
+        // the schedule is restarted and extended in whole units
+        let unit = if self.release_curve.is_stepwise() { self.buyback_internal } else { 1 };
+        let total_units = self.total_buyback_time / unit;
+        let pass_units = current_time.saturating_sub(self.start_time) / unit;
+        let extra_units = match mode {
+            TopUpMode::KeepEndTime => 0,
+            TopUpMode::ExtendEndTime => {
+                // round up, so the release rate never exceeds the current one
+                // a curve already released in full has no rate to extend the schedule by
+                require!(self.fund_amount > self.released_at_start, ERR153_NOTHING_LEFT_TO_RELEASE);
+                let schedule_amount = U256::from(self.fund_amount - self.released_at_start);
+                let extra_units = (U256::from(amount) * U256::from(total_units) + schedule_amount - 1) / schedule_amount;
+                require!(extra_units <= U256::from(u32::MAX), ERR109_SCHEDULE_OVERFLOW);
+                extra_units.as_u32()
+            }
+        };
+        let released_amount = self.released_amount(current_time);
+        self.start_time += pass_units * unit;
+        self.total_buyback_time = (total_units - pass_units).checked_add(extra_units)
+            .and_then(|units| units.checked_mul(unit))
+            .expect(ERR109_SCHEDULE_OVERFLOW);
+        self.released_at_start = released_amount;
+        self.fund_amount += amount;
+    }
+}
+
+impl Contract {
+    /// The time rounds are evaluated at, which stands still while the contract is paused.
+    pub fn internal_schedule_time(&self) -> u32 {
+        self.data().paused_at.unwrap_or_else(|| nano_to_sec(env::block_timestamp()))
+    }
+
+    /// Shift every queued round by the pause that just ended.
+    pub fn internal_shift_rounds_for_pause(&mut self, paused_at: u32, resumed_at: u32) {
+        let round_ids: Vec<u64> = self.data().round_queues.values().flatten().collect();
+        for round_id in round_ids {
+            let mut round = self.internal_get_round(round_id);
+            round.shift_for_pause(paused_at, resumed_at);
+            self.internal_set_round(round_id, &round);
+        }
+    }
+
+    pub fn internal_get_round(&self, round_id: u64) -> Round {
+        self.data().rounds.get(round_id).expect(ERR104_ROUND_NOT_FOUND)
+    }
+
+    pub fn internal_set_round(&mut self, round_id: u64, round: &Round) {
+        self.data_mut().rounds.replace(round_id, round);
+    }
+
+    /// The round at the head of the fund token's queue, which is the one do_buyback consumes.
+    pub fn internal_active_round_id(&self, fund_token_id: &AccountId) -> Option<u64> {
+        self.data().round_queues.get(fund_token_id)
+            .and_then(|round_queue| round_queue.first().copied())
+    }
+
+    pub fn internal_queue_round(&mut self, round: Round) -> u64 {
+        let round_id = self.data().rounds.len();
+        let mut round_queue = self.data().round_queues.get(&round.fund_token_id).unwrap_or_default();
+        round_queue.push(round_id);
+        self.data_mut().round_queues.insert(&round.fund_token_id, &round_queue);
+        self.data_mut().rounds.push(&round);
+        round_id
+    }
+
+    /// Remove the round from its fund token's queue, returning the position it had.
+    pub fn internal_dequeue_round(&mut self, fund_token_id: &AccountId, round_id: u64) -> usize {
+        let mut round_queue = self.data().round_queues.get(fund_token_id).unwrap_or_default();
+        let index = round_queue.iter().position(|id| *id == round_id).expect(ERR104_ROUND_NOT_FOUND);
+        round_queue.remove(index);
+        if round_queue.is_empty() {
+            self.data_mut().round_queues.remove(fund_token_id);
+        } else {
+            self.data_mut().round_queues.insert(fund_token_id, &round_queue);
+        }
+        index
+    }
+
+    /// Put a dequeued round back to the position it had in its fund token's queue.
+    pub fn internal_requeue_round(&mut self, fund_token_id: &AccountId, round_id: u64, index: usize) {
+        let mut round_queue = self.data().round_queues.get(fund_token_id).unwrap_or_default();
+        round_queue.insert(std::cmp::min(index, round_queue.len()), round_id);
+        self.data_mut().round_queues.insert(fund_token_id, &round_queue);
+    }
+
+    /// Hand the unspent fund of ended rounds at the head of the fund token's queue
+    /// over to the round behind them, as long as that round has roll_over_remainder set.
+    pub fn internal_roll_over_ended_rounds(&mut self, fund_token_id: &AccountId) {
+        let current_time = self.internal_schedule_time();
+        let round_queue = self.data().round_queues.get(fund_token_id).unwrap_or_default();
+        for round_ids in round_queue.windows(2) {
+            let (from_round_id, to_round_id) = (round_ids[0], round_ids[1]);
+            let mut from_round = self.internal_get_round(from_round_id);
+            let mut to_round = self.internal_get_round(to_round_id);
+            if current_time < from_round.end_time() || from_round.fund_locked > 0 || !to_round.roll_over_remainder {
+                break;
+            }
+            let amount = from_round.fund_amount - from_round.fund_cost;
+            from_round.rolled_over_amount = amount;
+            from_round.close(RoundStatus::Finished);
+            to_round.roll_in(amount, current_time);
+            self.internal_set_round(from_round_id, &from_round);
+            self.internal_set_round(to_round_id, &to_round);
+            self.internal_dequeue_round(fund_token_id, from_round_id);
+            events::emit::roll_over_round(from_round_id, to_round_id, fund_token_id, amount);
+        }
+    }
+
+    /// Move the fund token's active round out of its queue once all of its fund has been spent.
+    pub fn internal_try_finish_active_round(&mut self, fund_token_id: &AccountId) {
+        if let Some(round_id) = self.internal_active_round_id(fund_token_id) {
+            let mut round = self.internal_get_round(round_id);
+            if round.is_fully_spent() {
+                round.close(RoundStatus::Finished);
+                self.internal_set_round(round_id, &round);
+                self.internal_dequeue_round(fund_token_id, round_id);
+                log!("Buyback round {} finished", round_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "E153")]
+    fn extend_released_round() {
+        // everything is released from the start of the schedule
+        let mut round = Round::new(AccountId::new_unchecked("nusdt".to_string()), 100, 1000, 100, 10, ReleaseCurve::Piecewise { points: vec![(0, MAX_RATIO)] });
+        // rolling in the nothing left of a fully spent round keeps it fully released
+        round.roll_in(0, 1050);
+        assert_eq!((round.released_at_start, round.released_amount(1050)), (100, 100));
+        round.top_up(100, &TopUpMode::ExtendEndTime, 1060);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::*;
+
+/// Max number of swaps in a buyback route.
+pub const MAX_ROUTE_HOPS: usize = 3;
+/// Max number of routes a do_buyback splits its amount_in across, and of swaps in all of them together.
+pub const MAX_SPLIT_ROUTES: usize = 2;
+pub const MAX_SPLIT_HOPS: usize = 4;
+
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum RouteError {
+    EmptyRoute,
+    TooManyHops { hops: usize },
+    /// The swap at `index` doesn't take in what the swap before it puts out.
+    Discontinuous { index: usize },
+    WrongTokenOut { token_out: AccountId },
+    RepeatedPool { pool_id: u64 },
+    /// Only the first swap has an amount_in, the others take the output of the swap before.
+    InvalidAmountIn { index: usize },
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RouteError::EmptyRoute => write!(f, "{}", ERR121_EMPTY_ROUTE),
+            RouteError::TooManyHops { hops } => write!(f, "{}, {} > {}", ERR122_TOO_MANY_HOPS, hops, MAX_ROUTE_HOPS),
+            RouteError::Discontinuous { index } => write!(f, "{} at swap {}", ERR123_ROUTE_NOT_CONTINUOUS, index),
+            RouteError::WrongTokenOut { token_out } => write!(f, "{}, {}", ERR124_INVALID_ROUTE_TOKEN_OUT, token_out),
+            RouteError::RepeatedPool { pool_id } => write!(f, "{}, {}", ERR125_REPEATED_POOL, pool_id),
+            RouteError::InvalidAmountIn { index } => write!(f, "{} at swap {}", ERR126_INVALID_ROUTE_AMOUNT_IN, index),
+        }
+    }
+}
+
+/// The swaps of a swap_msg for the exchange.
+pub fn swap_actions(swap_msg: &str) -> Vec<Action> {
+    match serde_json::from_str::<SwapMessage>(swap_msg).expect(ERR100_WRONG_MSG_FORMAT) {
+        SwapMessage::Execute { actions, .. } => actions,
+    }
+}
+
+/// Check that `actions` is a chain of swaps ending in `buyback_token_id`.
+pub fn validate_route(actions: &[Action], buyback_token_id: &AccountId) -> Result<(), RouteError> {
+    if actions.is_empty() {
+        return Err(RouteError::EmptyRoute);
+    }
+    if actions.len() > MAX_ROUTE_HOPS {
+        return Err(RouteError::TooManyHops { hops: actions.len() });
+    }
+    let mut pool_ids = vec![];
+    let mut prev_token_out: Option<&AccountId> = None;
+    for (index, action) in actions.iter().enumerate() {
+        let Action::Swap(swap_action) = action;
+        if swap_action.amount_in.is_some() != (index == 0) {
+            return Err(RouteError::InvalidAmountIn { index });
+        }
+        if matches!(prev_token_out, Some(token_out) if *token_out != swap_action.token_in) {
+            return Err(RouteError::Discontinuous { index });
+        }
+        if pool_ids.contains(&swap_action.pool_id) {
+            return Err(RouteError::RepeatedPool { pool_id: swap_action.pool_id });
+        }
+        pool_ids.push(swap_action.pool_id);
+        prev_token_out = Some(&swap_action.token_out);
+    }
+    match prev_token_out {
+        Some(token_out) if token_out != buyback_token_id => Err(RouteError::WrongTokenOut { token_out: token_out.clone() }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn token(name: &str) -> AccountId {
+        AccountId::new_unchecked(name.to_string())
+    }
+
+    fn swap(pool_id: u64, token_in: &str, token_out: &str, amount_in: Option<u128>) -> Action {
+        Action::Swap(SwapAction {
+            pool_id,
+            token_in: token(token_in),
+            amount_in: amount_in.map(U128),
+            token_out: token(token_out),
+            min_amount_out: U128(0),
+        })
+    }
+
+    #[test]
+    fn valid_routes() {
+        assert_eq!(validate_route(&[swap(0, "usdt", "brrr", Some(1))], &token("brrr")), Ok(()));
+        assert_eq!(validate_route(&[
+            swap(0, "usdt", "usdc", Some(1)),
+            swap(1, "usdc", "wnear", None),
+            swap(2, "wnear", "brrr", None),
+        ], &token("brrr")), Ok(()));
+    }
+
+    #[test]
+    fn empty_route() {
+        assert_eq!(validate_route(&[], &token("brrr")), Err(RouteError::EmptyRoute));
+    }
+
+    #[test]
+    fn too_many_hops() {
+        let actions = vec![
+            swap(0, "usdt", "usdc", Some(1)),
+            swap(1, "usdc", "dai", None),
+            swap(2, "dai", "wnear", None),
+            swap(3, "wnear", "brrr", None),
+        ];
+        assert_eq!(validate_route(&actions, &token("brrr")), Err(RouteError::TooManyHops { hops: 4 }));
+    }
+
+    #[test]
+    fn discontinuous_route() {
+        let actions = vec![swap(0, "usdt", "usdc", Some(1)), swap(1, "wnear", "brrr", None)];
+        assert_eq!(validate_route(&actions, &token("brrr")), Err(RouteError::Discontinuous { index: 1 }));
+    }
+
+    #[test]
+    fn wrong_token_out() {
+        assert_eq!(
+            validate_route(&[swap(0, "usdt", "usdc", Some(1))], &token("brrr")),
+            Err(RouteError::WrongTokenOut { token_out: token("usdc") })
+        );
+        let actions = vec![swap(0, "usdt", "brrr", Some(1)), swap(1, "brrr", "usdc", None)];
+        assert_eq!(validate_route(&actions, &token("brrr")), Err(RouteError::WrongTokenOut { token_out: token("usdc") }));
+    }
+
+    #[test]
+    fn repeated_pool() {
+        let actions = vec![swap(0, "usdt", "usdc", Some(1)), swap(0, "usdc", "brrr", None)];
+        assert_eq!(validate_route(&actions, &token("brrr")), Err(RouteError::RepeatedPool { pool_id: 0 }));
+    }
+
+    #[test]
+    fn invalid_amount_in() {
+        assert_eq!(
+            validate_route(&[swap(0, "usdt", "brrr", None)], &token("brrr")),
+            Err(RouteError::InvalidAmountIn { index: 0 })
+        );
+        let actions = vec![swap(0, "usdt", "usdc", Some(1)), swap(1, "usdc", "brrr", Some(1))];
+        assert_eq!(validate_route(&actions, &token("brrr")), Err(RouteError::InvalidAmountIn { index: 1 }));
+    }
+}
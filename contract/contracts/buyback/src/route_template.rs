@@ -0,0 +1,179 @@
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct RouteHop {
+    pub pool_id: u64,
+    pub token_out: AccountId,
+}
+
+/// A pool path registered by the owner, which execute_registered_buyback builds its swaps from.
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct RouteTemplate {
+    pub fund_token_id: AccountId,
+    pub hops: Vec<RouteHop>,
+}
+
+impl RouteTemplate {
+    /// The swaps of the route, only the last one has a min_amount_out.
+    pub fn to_actions(&self, amount_in: u128, min_amount_out: u128) -> Vec<Action> {
+        let mut token_in = &self.fund_token_id;
+        self.hops.iter().enumerate().map(|(index, hop)| {
+            let action = Action::Swap(SwapAction {
+                pool_id: hop.pool_id,
+                token_in: token_in.clone(),
+                amount_in: if index == 0 { Some(U128(amount_in)) } else { None },
+                token_out: hop.token_out.clone(),
+                min_amount_out: U128(if index == self.hops.len() - 1 { min_amount_out } else { 0 }),
+            });
+            token_in = &hop.token_out;
+            action
+        }).collect()
+    }
+
+    pub fn to_swap_msg(&self, amount_in: u128, min_amount_out: u128) -> String {
+        serde_json::to_string(&SwapMessage::Execute {
+            referral_id: None,
+            actions: self.to_actions(amount_in, min_amount_out),
+        }).unwrap()
+    }
+}
+
+impl Contract {
+    pub fn internal_get_route_template(&self, route_id: u32) -> RouteTemplate {
+        self.data().route_templates.get(&route_id).expect(ERR135_ROUTE_NOT_FOUND)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Register the pool path from fund_token_id to the buyback token, returning its route id. Only can be called by owner.
+    #[payable]
+    pub fn register_route(&mut self, fund_token_id: AccountId, hops: Vec<RouteHop>) -> u32 {
+        assert_one_yocto();
+        self.assert_owner();
+        require!(self.data().token_white_list.contains(&fund_token_id), E005_INVALID_TOKEN);
+        let route_template = RouteTemplate { fund_token_id, hops };
+        if let Err(err) = validate_route(&route_template.to_actions(1, 1), &self.data().buyback_token_id) {
+            env::panic_str(&err.to_string());
+        }
+        let route_id = self.data().next_route_id;
+        self.data_mut().route_templates.insert(&route_id, &route_template);
+        self.data_mut().next_route_id += 1;
+        route_id
+    }
+
+    /// Remove a registered route. Only can be called by owner.
+    #[payable]
+    pub fn remove_route(&mut self, route_id: u32) {
+        assert_one_yocto();
+        self.assert_owner();
+        require!(self.data_mut().route_templates.remove(&route_id).is_some(), ERR135_ROUTE_NOT_FOUND);
+    }
+
+    /// Limit guardians to execute_registered_buyback, leaving the free-form swap_msg of do_buyback to the owner. Only can be called by owner.
+    #[payable]
+    pub fn set_registered_routes_only(&mut self, registered_routes_only: bool) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.data_mut().registered_routes_only = registered_routes_only;
+    }
+
+    /// do_buyback along a registered route, with the swaps built by the contract.
+    pub fn execute_registered_buyback(&mut self, route_id: u32, amount_in: U128, min_amount_out: U128) {
+        self.assert_contract_running();
+        self.assert_owner_or_guardians();
+        let route_template = self.internal_get_route_template(route_id);
+        self.internal_do_buyback(vec![route_template.to_swap_msg(amount_in.0, min_amount_out.0)]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::test::*;
+    use near_sdk::PromiseResult;
+
+    fn token(name: &str) -> AccountId {
+        AccountId::new_unchecked(name.to_string())
+    }
+
+    #[test]
+    fn template_to_actions() {
+        let route_template = RouteTemplate {
+            fund_token_id: token("usdt"),
+            hops: vec![
+                RouteHop { pool_id: 3, token_out: token("usdc") },
+                RouteHop { pool_id: 7, token_out: token("brrr") },
+            ],
+        };
+        let actions = route_template.to_actions(100, 90);
+        assert_eq!(validate_route(&actions, &token("brrr")), Ok(()));
+        let Action::Swap(first_swap) = &actions[0];
+        assert_eq!((first_swap.pool_id, first_swap.amount_in, first_swap.min_amount_out), (3, Some(U128(100)), U128(0)));
+        let Action::Swap(last_swap) = &actions[1];
+        assert_eq!(last_swap.token_in, token("usdc"));
+        assert_eq!((last_swap.pool_id, last_swap.amount_in, last_swap.min_amount_out), (7, None, U128(90)));
+    }
+
+    #[test]
+    fn registered_route() {
+        let (mut context, mut contract) = setup_contract();
+        let route_id = contract.register_route(nusdt(), vec![
+            RouteHop { pool_id: 3, token_out: nusdc() },
+            RouteHop { pool_id: 7, token_out: buyback_token_id() },
+        ]);
+        assert_eq!(contract.list_routes(Some(nusdt())).len(), 1);
+        assert!(contract.list_routes(Some(nusdc())).is_empty());
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(owner_id()).build());
+        contract.execute_registered_buyback(route_id, U128(d(40, 6)), U128(MIN_AMOUNT_OUT));
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(60, 6));
+        assert_eq!(contract.get_metadata().pending_operation, Some(PendingOperation::Buyback));
+
+        testing_env!(context.predecessor_account_id(owner_id()).attached_deposit(1).build());
+        contract.remove_route(route_id);
+        assert!(contract.get_route(route_id).is_none());
+    }
+
+    #[test]
+    fn registered_routes_only_off_by_default() {
+        let (mut context, mut contract) = setup_contract();
+        contract.extend_guardians(vec![guardian_id()]);
+        assert!(!contract.get_metadata().registered_routes_only);
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+
+        // a guardian may pass any swap_msg until the owner turns it on
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(guardian_id()).attached_deposit(0).build());
+        contract.do_buyback(swap_msg(nusdt(), d(10, 6)), None);
+        assert_eq!(contract.get_metadata().pending_operation, Some(PendingOperation::Buyback));
+    }
+
+    #[test]
+    #[should_panic(expected = "E151")]
+    fn registered_routes_only() {
+        let (mut context, mut contract) = setup_contract();
+        contract.extend_guardians(vec![guardian_id()]);
+        let route_id = contract.register_route(nusdt(), vec![RouteHop { pool_id: 0, token_out: buyback_token_id() }]);
+        contract.set_registered_routes_only(true);
+        assert!(contract.get_metadata().registered_routes_only);
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+
+        // the guardian still buys back along the registered route, and the owner with any swap_msg
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(guardian_id()).attached_deposit(0).build());
+        contract.execute_registered_buyback(route_id, U128(d(10, 6)), U128(MIN_AMOUNT_OUT));
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Failed);
+        contract.callback_quote_buyback(0, U128(d(10, 6)), 1100, vec![swap_msg(nusdt(), d(10, 6))], 0, U128(0));
+        testing_env!(context.predecessor_account_id(owner_id()).build());
+        contract.do_buyback(swap_msg(nusdt(), d(10, 6)), None);
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Failed);
+        contract.callback_quote_buyback(0, U128(d(10, 6)), 1100, vec![swap_msg(nusdt(), d(10, 6))], 0, U128(0));
+        testing_env!(context.predecessor_account_id(guardian_id()).build());
+        contract.do_buyback(swap_msg(nusdt(), d(10, 6)), None);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::*;
+
+/// How far in the past a new round may start, to tolerate the transaction delay. unit: sec
+pub const MAX_START_TIME_LAG: u32 = 3600;
+/// How far in the future a new round may start. unit: sec
+pub const MAX_START_TIME_DELAY: u32 = 30 * 24 * 3600;
+
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum ScheduleError {
+    ZeroInterval,
+    ZeroDuration,
+    DurationNotMultipleOfInterval { total_buyback_time: u32, buyback_internal: u32 },
+    StartTimeTooEarly { start_time: u32, earliest_start_time: u32 },
+    StartTimeTooLate { start_time: u32, latest_start_time: u32 },
+    EndTimeOverflow,
+    InvalidReleaseCurve,
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScheduleError::ZeroInterval => write!(f, "{}", ERR111_ZERO_BUYBACK_INTERNAL),
+            ScheduleError::ZeroDuration => write!(f, "{}", ERR112_ZERO_BUYBACK_TIME),
+            ScheduleError::DurationNotMultipleOfInterval { total_buyback_time, buyback_internal } => write!(
+                f, "{}, {} % {} != 0", ERR113_BUYBACK_TIME_NOT_MULTIPLE_OF_INTERNAL, total_buyback_time, buyback_internal
+            ),
+            ScheduleError::StartTimeTooEarly { start_time, earliest_start_time } => write!(
+                f, "{}, {} < {}", ERR114_START_TIME_TOO_EARLY, start_time, earliest_start_time
+            ),
+            ScheduleError::StartTimeTooLate { start_time, latest_start_time } => write!(
+                f, "{}, {} > {}", ERR115_START_TIME_TOO_LATE, start_time, latest_start_time
+            ),
+            ScheduleError::EndTimeOverflow => write!(f, "{}", ERR109_SCHEDULE_OVERFLOW),
+            ScheduleError::InvalidReleaseCurve => write!(f, "{}", ERR110_INVALID_RELEASE_CURVE),
+        }
+    }
+}
+
+/// Check the schedule of a new round against `current_time`.
+pub fn validate_schedule(
+    start_time: u32,
+    total_buyback_time: u32,
+    buyback_internal: u32,
+    release_curve: &ReleaseCurve,
+    current_time: u32,
+) -> Result<(), ScheduleError> {
+    if buyback_internal == 0 {
+        return Err(ScheduleError::ZeroInterval);
+    }
+    if total_buyback_time == 0 {
+        return Err(ScheduleError::ZeroDuration);
+    }
+    if total_buyback_time.checked_rem(buyback_internal) != Some(0) {
+        return Err(ScheduleError::DurationNotMultipleOfInterval { total_buyback_time, buyback_internal });
+    }
+    let earliest_start_time = current_time.saturating_sub(MAX_START_TIME_LAG);
+    if start_time < earliest_start_time {
+        return Err(ScheduleError::StartTimeTooEarly { start_time, earliest_start_time });
+    }
+    let latest_start_time = current_time.saturating_add(MAX_START_TIME_DELAY);
+    if start_time > latest_start_time {
+        return Err(ScheduleError::StartTimeTooLate { start_time, latest_start_time });
+    }
+    if start_time.checked_add(total_buyback_time).is_none() {
+        return Err(ScheduleError::EndTimeOverflow);
+    }
+    release_curve.validate()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const NOW: u32 = 1_700_000_000;
+
+    #[test]
+    fn valid_schedule() {
+        assert_eq!(validate_schedule(NOW, 86400, 7200, &ReleaseCurve::Stepwise, NOW), Ok(()));
+        assert_eq!(validate_schedule(NOW - MAX_START_TIME_LAG, 100, 10, &ReleaseCurve::Linear, NOW), Ok(()));
+        assert_eq!(validate_schedule(NOW + MAX_START_TIME_DELAY, 100, 100, &ReleaseCurve::Stepwise, NOW), Ok(()));
+    }
+
+    #[test]
+    fn invalid_schedule() {
+        assert_eq!(validate_schedule(NOW, 100, 0, &ReleaseCurve::Stepwise, NOW), Err(ScheduleError::ZeroInterval));
+        assert_eq!(validate_schedule(NOW, 0, 10, &ReleaseCurve::Stepwise, NOW), Err(ScheduleError::ZeroDuration));
+        assert_eq!(
+            validate_schedule(NOW, 105, 10, &ReleaseCurve::Stepwise, NOW),
+            Err(ScheduleError::DurationNotMultipleOfInterval { total_buyback_time: 105, buyback_internal: 10 })
+        );
+        assert_eq!(
+            validate_schedule(NOW - MAX_START_TIME_LAG - 1, 100, 10, &ReleaseCurve::Stepwise, NOW),
+            Err(ScheduleError::StartTimeTooEarly { start_time: NOW - MAX_START_TIME_LAG - 1, earliest_start_time: NOW - MAX_START_TIME_LAG })
+        );
+        assert_eq!(
+            validate_schedule(NOW + MAX_START_TIME_DELAY + 1, 100, 10, &ReleaseCurve::Stepwise, NOW),
+            Err(ScheduleError::StartTimeTooLate { start_time: NOW + MAX_START_TIME_DELAY + 1, latest_start_time: NOW + MAX_START_TIME_DELAY })
+        );
+        assert_eq!(
+            validate_schedule(u32::MAX - 10, 20, 10, &ReleaseCurve::Stepwise, u32::MAX - 10),
+            Err(ScheduleError::EndTimeOverflow)
+        );
+        assert_eq!(
+            validate_schedule(NOW, 100, 10, &ReleaseCurve::ExponentialDecay { decay_bps: 0 }, NOW),
+            Err(ScheduleError::InvalidReleaseCurve)
+        );
+        assert!(ScheduleError::ZeroInterval.to_string().starts_with("E111"));
+    }
+}
@@ -0,0 +1,375 @@
+use crate::*;
+use near_sdk::{promise_result_as_success, Promise, PromiseResult};
+
+pub const GAS_FOR_SIMULATE_CALLBACK: Gas = Gas(10 * Gas::ONE_TERA.0);
+
+/// What do_buyback with the routes would do now, by the exchange quotes.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Deserialize, Debug))]
+pub struct BuybackSimulation {
+    // quote of each swap of the routes, route after route
+    pub hop_quotes: Vec<U128>,
+    pub expected_amount_out: Option<U128>,
+    // fund spent per buyback token at the quote, scaled by PRICE_PRECISION
+    pub expected_price: Option<U128>,
+    pub oracle_price: Option<U128>,
+    // errors do_buyback would stop at, empty if it would go through
+    pub failed_checks: Vec<String>,
+}
+
+impl Contract {
+    /// The errors do_buyback would stop at for the routes right now, without the caller check and the pool liquidity check.
+    /// `route_quotes` is what the exchange returns for each route, None skips the checks against them.
+    pub fn internal_buyback_checks(&self, routes: &[Vec<Action>], route_quotes: Option<&[u128]>) -> Vec<String> {
+        let mut failed_checks = vec![];
+        if self.data().state != RunningState::Running {
+            failed_checks.push(ERR6_CONTRACT_PAUSED.to_string());
+        }
+        if self.data().pending_operation.is_some() {
+            failed_checks.push(ERR119_OPERATION_IN_PROGRESS.to_string());
+        }
+        if self.internal_transfers_in_flight() {
+            failed_checks.push(ERR152_TRANSFERS_IN_FLIGHT.to_string());
+        }
+        if routes.is_empty() || routes.len() > MAX_SPLIT_ROUTES || routes.iter().map(|actions| actions.len()).sum::<usize>() > MAX_SPLIT_HOPS {
+            failed_checks.push(ERR141_TOO_MANY_SPLIT_ROUTES.to_string());
+            return failed_checks;
+        }
+        for actions in routes.iter() {
+            if let Err(err) = validate_route(actions, &self.data().buyback_token_id) {
+                failed_checks.push(err.to_string());
+                return failed_checks;
+            }
+        }
+        let mut pool_ids = vec![];
+        for action in routes.iter().flatten() {
+            let Action::Swap(swap_action) = action;
+            if !self.internal_is_pool_allowed(swap_action.pool_id) {
+                add_failed_check(&mut failed_checks, ERR139_POOL_NOT_WHITELISTED);
+            }
+            if pool_ids.contains(&swap_action.pool_id) {
+                add_failed_check(&mut failed_checks, ERR125_REPEATED_POOL);
+            }
+            pool_ids.push(swap_action.pool_id);
+        }
+        // (amount_in, min_amount_out) of each route
+        let route_amounts: Vec<(u128, u128)> = routes.iter().map(|actions| {
+            let Action::Swap(first_swap) = &actions[0];
+            let Action::Swap(last_swap) = &actions[actions.len() - 1];
+            (first_swap.amount_in.map(|amount| amount.0).unwrap_or(0), last_swap.min_amount_out.0)
+        }).collect();
+        if route_amounts.iter().any(|(_, min_amount_out)| *min_amount_out == 0) {
+            failed_checks.push(ERR127_ZERO_MIN_AMOUNT_OUT.to_string());
+        }
+        let fund_token_id = route_fund_token_id(&routes[0]);
+        if !routes.iter().all(|actions| route_fund_token_id(actions) == fund_token_id) {
+            failed_checks.push(ERR142_SPLIT_FUND_TOKEN_MISMATCH.to_string());
+        }
+        if self.data().price_oracle_id.is_some() {
+            let fund_price = self.internal_fresh_oracle_price(fund_token_id);
+            let buyback_price = self.internal_fresh_oracle_price(&self.data().buyback_token_id);
+            match (fund_price, buyback_price) {
+                (Some(fund_price), Some(buyback_price)) => for (amount_in, min_amount_out) in route_amounts.iter() {
+                    match oracle_amount_out(*amount_in, &fund_price, &buyback_price) {
+                        Some(oracle_amount_out) if *min_amount_out < ratio(oracle_amount_out, MAX_RATIO - self.data().max_slippage_bps) => {
+                            add_failed_check(&mut failed_checks, ERR132_MIN_AMOUNT_OUT_BELOW_ORACLE);
+                        }
+                        Some(_) => (),
+                        None => add_failed_check(&mut failed_checks, ERR131_STALE_ORACLE_PRICE),
+                    }
+                },
+                _ => failed_checks.push(ERR131_STALE_ORACLE_PRICE.to_string()),
+            }
+        }
+
+        // do_buyback rolls ended rounds over first, which only adds to the active round
+        let round = match self.internal_active_round_id(fund_token_id) {
+            Some(round_id) => self.internal_get_round(round_id),
+            None => {
+                failed_checks.push(ERR103_NO_ACTIVE_ROUND.to_string());
+                return failed_checks;
+            }
+        };
+        let amount_in: u128 = route_amounts.iter().map(|(amount_in, _)| amount_in).sum();
+        if route_amounts.iter().any(|(amount_in, _)| *amount_in == 0) || amount_in > round.available_fund_amount(self.internal_schedule_time()) {
+            failed_checks.push("Invalid amount_in".to_string());
+        }
+        if route_amounts.iter().any(|(amount_in, min_amount_out)| round.exceeds_max_price(*amount_in, *min_amount_out)) {
+            failed_checks.push(ERR133_PRICE_ABOVE_MAX_PRICE.to_string());
+        }
+
+        let current_time = nano_to_sec(env::block_timestamp());
+        let spend_cap = self.data().spend_caps.get(fund_token_id).unwrap_or_default();
+        let spend_record = self.internal_get_spend_record(fund_token_id);
+        if matches!(spend_cap.max_amount_in, Some(max_amount_in) if amount_in > max_amount_in) {
+            failed_checks.push(ERR116_EXCEED_MAX_AMOUNT_IN.to_string());
+        }
+        if matches!(spend_cap.max_daily_amount, Some(max_daily_amount) if spend_record.daily_amount() + amount_in > max_daily_amount) {
+            failed_checks.push(ERR117_EXCEED_DAILY_SPEND.to_string());
+        }
+        if matches!(spend_record.last_buyback_time, Some(last_buyback_time) if current_time < last_buyback_time.saturating_add(spend_cap.min_buyback_gap)) {
+            failed_checks.push(ERR118_BUYBACK_TOO_SOON.to_string());
+        }
+
+        if let Some(route_quotes) = route_quotes {
+            let min_amount_out_too_low = route_amounts.iter().zip(route_quotes.iter())
+                .any(|((_, min_amount_out), quote)| *min_amount_out < ratio(*quote, MAX_RATIO - self.data().max_slippage_bps));
+            if min_amount_out_too_low {
+                failed_checks.push(ERR129_MIN_AMOUNT_OUT_TOO_LOW.to_string());
+            }
+            if round.exceeds_max_price(amount_in, route_quotes.iter().sum()) {
+                add_failed_check(&mut failed_checks, ERR133_PRICE_ABOVE_MAX_PRICE);
+            }
+        }
+        failed_checks
+    }
+
+    /// Quote the swap at `hop` of a simulated buyback, with the swaps of all routes counted one after another.
+    /// The callback carries on with the next one.
+    fn internal_simulate_hop(&self, swap_msgs: Vec<String>, routes: &[Vec<Action>], hop: usize, hop_amount_in: u128, hop_quotes: Vec<U128>, pool_checks: Vec<String>) -> Promise {
+        let (route, route_hop) = locate_hop(routes, hop);
+        let Action::Swap(swap_action) = &routes[route][route_hop];
+        let hops_left = (routes.iter().flatten().count() - hop - 1) as u64;
+        let simulate_callback_gas = GAS_FOR_SIMULATE_CALLBACK.0 * (hops_left + 1) + GAS_FOR_GET_RETURN.0 * hops_left;
+        ext_ref_exchange::ext(self.data().ref_exchange_id.clone())
+            .with_static_gas(GAS_FOR_GET_RETURN)
+            .get_return(
+                swap_action.pool_id,
+                swap_action.token_in.clone(),
+                U128(hop_amount_in),
+                swap_action.token_out.clone()
+            ).then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(Gas(simulate_callback_gas))
+                    .callback_simulate_buyback(swap_msgs, hop as u32, hop_quotes, pool_checks)
+            )
+    }
+
+    /// What the routes are quoted, the quote of the last swap of each route.
+    fn internal_simulation_result(&self, routes: &[Vec<Action>], hop_quotes: Vec<U128>, mut failed_checks: Vec<String>) -> BuybackSimulation {
+        let fund_token_id = route_fund_token_id(&routes[0]);
+        let mut route_quotes = vec![];
+        let mut last_hop = 0;
+        for actions in routes.iter() {
+            last_hop += actions.len();
+            if let Some(quote) = hop_quotes.get(last_hop - 1) {
+                route_quotes.push(quote.0);
+            }
+        }
+        if route_quotes.len() < routes.len() {
+            for check in self.internal_buyback_checks(routes, None) {
+                add_failed_check(&mut failed_checks, &check);
+            }
+            add_failed_check(&mut failed_checks, ERR102_CROSS_CONTRACT_FAILED);
+            return BuybackSimulation {
+                hop_quotes,
+                expected_amount_out: None,
+                expected_price: None,
+                oracle_price: self.internal_current_oracle_price(fund_token_id).map(U128),
+                failed_checks,
+            };
+        }
+        for check in self.internal_buyback_checks(routes, Some(&route_quotes)) {
+            add_failed_check(&mut failed_checks, &check);
+        }
+        let amount_in: u128 = routes.iter().map(|actions| {
+            let Action::Swap(first_swap) = &actions[0];
+            first_swap.amount_in.map(|amount| amount.0).unwrap_or(0)
+        }).sum();
+        let quote: u128 = route_quotes.iter().sum();
+        BuybackSimulation {
+            hop_quotes,
+            expected_amount_out: Some(U128(quote)),
+            expected_price: if quote > 0 { Some(U128(u128_ratio(amount_in, PRICE_PRECISION, quote))) } else { None },
+            oracle_price: self.internal_current_oracle_price(fund_token_id).map(U128),
+            failed_checks,
+        }
+    }
+}
+
+fn add_failed_check(failed_checks: &mut Vec<String>, check: &str) {
+    if !failed_checks.iter().any(|failed_check| failed_check == check) {
+        failed_checks.push(check.to_string());
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Dry run do_buyback with swap_msg and split_swap_msgs against the exchange quotes, nothing is locked or spent.
+    /// It cross-calls the exchange, so it has to be sent as a transaction rather than a view call.
+    pub fn simulate_buyback(&self, swap_msg: String, split_swap_msgs: Option<Vec<String>>) -> PromiseOrValue<BuybackSimulation> {
+        let mut swap_msgs = vec![swap_msg];
+        swap_msgs.extend(split_swap_msgs.unwrap_or_default());
+        let routes: Vec<Vec<Action>> = swap_msgs.iter().map(|swap_msg| swap_actions(swap_msg)).collect();
+        let failed_checks = self.internal_buyback_checks(&routes, None);
+        // a route do_buyback can't read or quote is reported as is
+        let is_route_invalid = routes.len() > MAX_SPLIT_ROUTES
+            || routes.iter().map(|actions| actions.len()).sum::<usize>() > MAX_SPLIT_HOPS
+            || routes.iter().any(|actions| validate_route(actions, &self.data().buyback_token_id).is_err());
+        if is_route_invalid {
+            return PromiseOrValue::Value(BuybackSimulation {
+                hop_quotes: vec![],
+                expected_amount_out: None,
+                expected_price: None,
+                oracle_price: None,
+                failed_checks,
+            });
+        }
+        if self.internal_needs_pool_check(&routes) {
+            return PromiseOrValue::Promise(self.internal_simulate_check_pools(swap_msgs, &routes));
+        }
+        let Action::Swap(first_swap) = &routes[0][0];
+        let amount_in = first_swap.amount_in.expect(ERR100_WRONG_MSG_FORMAT).0;
+        PromiseOrValue::Promise(self.internal_simulate_hop(swap_msgs, &routes, 0, amount_in, vec![], vec![]))
+    }
+
+    #[private]
+    pub fn callback_simulate_check_pools(&self, swap_msgs: Vec<String>) -> PromiseOrValue<BuybackSimulation> {
+        let routes: Vec<Vec<Action>> = swap_msgs.iter().map(|swap_msg| swap_actions(swap_msg)).collect();
+        let mut pool_checks = vec![];
+        for (index, action) in routes.iter().flatten().enumerate() {
+            let Action::Swap(swap_action) = action;
+            let pool_info = match env::promise_result(index as u64) {
+                PromiseResult::Successful(cross_call_result) => serde_json::from_slice::<RefPoolInfo>(&cross_call_result).ok(),
+                _ => None,
+            };
+            match pool_info {
+                Some(pool_info) => if self.internal_shallow_pool_token(swap_action, &pool_info).is_some() {
+                    add_failed_check(&mut pool_checks, ERR140_POOL_LIQUIDITY_TOO_LOW);
+                },
+                None => add_failed_check(&mut pool_checks, ERR102_CROSS_CONTRACT_FAILED),
+            }
+        }
+        let Action::Swap(first_swap) = &routes[0][0];
+        let amount_in = first_swap.amount_in.expect(ERR100_WRONG_MSG_FORMAT).0;
+        PromiseOrValue::Promise(self.internal_simulate_hop(swap_msgs, &routes, 0, amount_in, vec![], pool_checks))
+    }
+
+    #[private]
+    pub fn callback_simulate_buyback(&self, swap_msgs: Vec<String>, hop: u32, hop_quotes: Vec<U128>, pool_checks: Vec<String>) -> PromiseOrValue<BuybackSimulation> {
+        let routes: Vec<Vec<Action>> = swap_msgs.iter().map(|swap_msg| swap_actions(swap_msg)).collect();
+        let quote = promise_result_as_success()
+            .and_then(|cross_call_result| serde_json::from_slice::<U128>(&cross_call_result).ok());
+        let mut hop_quotes = hop_quotes;
+        let quote = match quote {
+            Some(quote) => quote.0,
+            None => return PromiseOrValue::Value(self.internal_simulation_result(&routes, hop_quotes, pool_checks)),
+        };
+        hop_quotes.push(U128(quote));
+        let (route, route_hop) = locate_hop(&routes, hop as usize);
+        let next_hop = hop as usize + 1;
+        if route_hop + 1 < routes[route].len() {
+            return PromiseOrValue::Promise(self.internal_simulate_hop(swap_msgs, &routes, next_hop, quote, hop_quotes, pool_checks));
+        }
+        // the next route starts from its own amount_in
+        if route + 1 < routes.len() {
+            let Action::Swap(first_swap) = &routes[route + 1][0];
+            let route_amount_in = first_swap.amount_in.expect(ERR100_WRONG_MSG_FORMAT).0;
+            return PromiseOrValue::Promise(self.internal_simulate_hop(swap_msgs, &routes, next_hop, route_amount_in, hop_quotes, pool_checks));
+        }
+        PromiseOrValue::Value(self.internal_simulation_result(&routes, hop_quotes, pool_checks))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::test::*;
+    use near_sdk::{PromiseResult, RuntimeFeesConfig, VMConfig};
+    use std::collections::HashMap;
+
+    #[test]
+    fn simulate_buyback() {
+        let (mut context, mut contract) = setup_contract();
+        contract.set_spend_cap(nusdt(), Some(U128(d(30, 6))), None, None);
+        fund_round(&mut contract, &mut context, buyback_info_with_max_price(1000, 100, 10, d(50, 6)));
+
+        // 20 nusdt quoted at 1 buyback token goes through
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(contract_account_id()).build());
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(MIN_AMOUNT_OUT)).unwrap()));
+        let simulation = match contract.callback_simulate_buyback(vec![swap_msg(nusdt(), d(20, 6))], 0, vec![], vec![]) {
+            PromiseOrValue::Value(simulation) => simulation,
+            PromiseOrValue::Promise(_) => panic!("single swap route quoted twice"),
+        };
+        assert_eq!(simulation.hop_quotes, vec![U128(MIN_AMOUNT_OUT)]);
+        assert_eq!(simulation.expected_amount_out, Some(U128(MIN_AMOUNT_OUT)));
+        assert_eq!(simulation.expected_price, Some(U128(d(20, 6))));
+        assert!(simulation.failed_checks.is_empty());
+
+        // 40 nusdt is over the spend cap, and the quote is above the max price
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(MIN_AMOUNT_OUT / 2)).unwrap()));
+        let simulation = match contract.callback_simulate_buyback(vec![swap_msg(nusdt(), d(40, 6))], 0, vec![], vec![]) {
+            PromiseOrValue::Value(simulation) => simulation,
+            PromiseOrValue::Promise(_) => panic!("single swap route quoted twice"),
+        };
+        assert_eq!(simulation.failed_checks, vec![
+            ERR116_EXCEED_MAX_AMOUNT_IN.to_string(),
+            ERR133_PRICE_ABOVE_MAX_PRICE.to_string(),
+        ]);
+        // nothing is locked by the simulation
+        assert_eq!(contract.get_available_fund_amount(nusdt()).0, d(100, 6));
+        assert_eq!(contract.get_metadata().pending_operation, None);
+
+        // an invalid route is not quoted
+        testing_env!(context.predecessor_account_id(owner_id()).build());
+        let route_template = RouteTemplate { fund_token_id: nusdt(), hops: vec![RouteHop { pool_id: 0, token_out: nusdc() }] };
+        let simulation = match contract.simulate_buyback(route_template.to_swap_msg(d(20, 6), MIN_AMOUNT_OUT), None) {
+            PromiseOrValue::Value(simulation) => simulation,
+            PromiseOrValue::Promise(_) => panic!("invalid route quoted"),
+        };
+        assert!(simulation.hop_quotes.is_empty());
+        assert_eq!(simulation.failed_checks, vec![RouteError::WrongTokenOut { token_out: nusdc() }.to_string()]);
+    }
+
+    #[test]
+    fn simulate_split_buyback() {
+        let (mut context, mut contract) = setup_contract();
+        contract.set_min_pool_liquidity(nusdc(), Some(U128(d(10000, 6))));
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+
+        let direct_route = RouteTemplate { fund_token_id: nusdt(), hops: vec![RouteHop { pool_id: 0, token_out: buyback_token_id() }] };
+        let stable_route = RouteTemplate { fund_token_id: nusdt(), hops: vec![
+            RouteHop { pool_id: 1, token_out: nusdc() },
+            RouteHop { pool_id: 2, token_out: buyback_token_id() },
+        ] };
+        let swap_msgs = vec![direct_route.to_swap_msg(d(30, 6), d(3, 18)), stable_route.to_swap_msg(d(20, 6), d(2, 18))];
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(owner_id()).build());
+        assert!(matches!(contract.simulate_buyback(swap_msgs[0].clone(), Some(vec![swap_msgs[1].clone()])), PromiseOrValue::Promise(_)));
+
+        // the shallow nusdc side of pool 1 is noted, and the routes are quoted anyway
+        context.predecessor_account_id(contract_account_id());
+        testing_env!(context.build(), VMConfig::test(), RuntimeFeesConfig::test(), HashMap::default(), vec![
+            pool_result(vec![(nusdt(), d(20000, 6)), (buyback_token_id(), d(20000, 18))]),
+            pool_result(vec![(nusdt(), d(20000, 6)), (nusdc(), d(5000, 6))]),
+            pool_result(vec![(nusdc(), d(20000, 6)), (buyback_token_id(), d(20000, 18))]),
+        ]);
+        assert!(matches!(contract.callback_simulate_check_pools(swap_msgs.clone()), PromiseOrValue::Promise(_)));
+        let pool_checks = vec![ERR140_POOL_LIQUIDITY_TOO_LOW.to_string()];
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(3, 18))).unwrap()));
+        let hop_quotes = match contract.callback_simulate_buyback(swap_msgs.clone(), 0, vec![], pool_checks.clone()) {
+            PromiseOrValue::Promise(_) => vec![U128(d(3, 18))],
+            PromiseOrValue::Value(_) => panic!("stable route not quoted"),
+        };
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(20, 6))).unwrap()));
+        assert!(matches!(contract.callback_simulate_buyback(swap_msgs.clone(), 1, hop_quotes.clone(), pool_checks.clone()), PromiseOrValue::Promise(_)));
+        // the stable route is quoted 4, its min_amount_out of 2 is below the max slippage
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(4, 18))).unwrap()));
+        let hop_quotes = vec![U128(d(3, 18)), U128(d(20, 6))];
+        let simulation = match contract.callback_simulate_buyback(swap_msgs.clone(), 2, hop_quotes, pool_checks) {
+            PromiseOrValue::Value(simulation) => simulation,
+            PromiseOrValue::Promise(_) => panic!("split route quoted past its last swap"),
+        };
+        assert_eq!(simulation.hop_quotes, vec![U128(d(3, 18)), U128(d(20, 6)), U128(d(4, 18))]);
+        assert_eq!(simulation.expected_amount_out, Some(U128(d(7, 18))));
+        assert_eq!(simulation.failed_checks, vec![
+            ERR140_POOL_LIQUIDITY_TOO_LOW.to_string(),
+            ERR129_MIN_AMOUNT_OUT_TOO_LOW.to_string(),
+        ]);
+
+        // routes sharing a pool are reported like do_buyback rejects them
+        let other_direct_route = direct_route.to_swap_msg(d(10, 6), d(1, 18));
+        let failed_checks = contract.internal_buyback_checks(&[swap_actions(&swap_msgs[0]), swap_actions(&other_direct_route)], None);
+        assert_eq!(failed_checks, vec![ERR125_REPEATED_POOL.to_string()]);
+    }
+}
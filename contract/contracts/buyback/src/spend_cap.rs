@@ -0,0 +1,162 @@
+use crate::*;
+
+/// Length of the rolling window max_daily_amount applies to. unit: sec
+pub const SPEND_WINDOW: u32 = 24 * 3600;
+
+/// Limits on how fast a fund token can be spent by do_buyback, None means unlimited.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct SpendCap {
+    // max amount_in of a single do_buyback
+    pub max_amount_in: Option<u128>,
+    // max amount_in of all do_buyback in the last SPEND_WINDOW
+    pub max_daily_amount: Option<u128>,
+    // min time between two do_buyback. unit: sec
+    pub min_buyback_gap: u32,
+}
+
+/// What do_buyback has spent of a fund token recently.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct SpendRecord {
+    // (time, amount_in) of the do_buyback within the last SPEND_WINDOW, oldest first
+    pub spends: Vec<(u32, u128)>,
+    pub last_buyback_time: Option<u32>,
+}
+
+impl SpendRecord {
+    pub fn prune(&mut self, current_time: u32) {
+        let window_start = current_time.saturating_sub(SPEND_WINDOW);
+        self.spends.retain(|(time, _)| *time > window_start);
+    }
+
+    pub fn daily_amount(&self) -> u128 {
+        self.spends.iter().map(|(_, amount)| amount).sum()
+    }
+
+    /// Give back the part of a spend at `time` that the exchange didn't use.
+    pub fn refund(&mut self, time: u32, amount: u128) {
+        if let Some(spend) = self.spends.iter_mut().find(|(t, a)| *t == time && *a >= amount) {
+            spend.1 -= amount;
+        }
+    }
+}
+
+impl Contract {
+    pub fn internal_get_spend_record(&self, fund_token_id: &AccountId) -> SpendRecord {
+        let mut spend_record = self.data().spend_records.get(fund_token_id).unwrap_or_default();
+        spend_record.prune(nano_to_sec(env::block_timestamp()));
+        spend_record
+    }
+
+    /// Check amount_in against the fund token's spend cap and record it.
+    pub fn internal_spend(&mut self, fund_token_id: &AccountId, amount_in: u128, current_time: u32) {
+        let spend_cap = self.data().spend_caps.get(fund_token_id).unwrap_or_default();
+        let mut spend_record = self.internal_get_spend_record(fund_token_id);
+        if let Some(max_amount_in) = spend_cap.max_amount_in {
+            require!(amount_in <= max_amount_in, ERR116_EXCEED_MAX_AMOUNT_IN);
+        }
+        if let Some(max_daily_amount) = spend_cap.max_daily_amount {
+            require!(spend_record.daily_amount() + amount_in <= max_daily_amount, ERR117_EXCEED_DAILY_SPEND);
+        }
+        if let Some(last_buyback_time) = spend_record.last_buyback_time {
+            require!(current_time >= last_buyback_time.saturating_add(spend_cap.min_buyback_gap), ERR118_BUYBACK_TOO_SOON);
+        }
+        spend_record.spends.push((current_time, amount_in));
+        spend_record.last_buyback_time = Some(current_time);
+        self.data_mut().spend_records.insert(fund_token_id, &spend_record);
+    }
+
+    pub fn internal_refund_spend(&mut self, fund_token_id: &AccountId, spend_time: u32, amount: u128) {
+        if let Some(mut spend_record) = self.data().spend_records.get(fund_token_id) {
+            spend_record.refund(spend_time, amount);
+            self.data_mut().spend_records.insert(fund_token_id, &spend_record);
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Set the spend cap of a fund token, None means unlimited. Only can be called by owner.
+    #[payable]
+    pub fn set_spend_cap(&mut self, token_id: AccountId, max_amount_in: Option<U128>, max_daily_amount: Option<U128>, min_buyback_gap: Option<u32>) {
+        assert_one_yocto();
+        self.assert_owner();
+        let spend_cap = SpendCap {
+            max_amount_in: max_amount_in.map(|amount| amount.0),
+            max_daily_amount: max_daily_amount.map(|amount| amount.0),
+            min_buyback_gap: min_buyback_gap.unwrap_or(0),
+        };
+        self.data_mut().spend_caps.insert(&token_id, &spend_cap);
+    }
+
+    /// Remove the spend cap of a fund token. Only can be called by owner.
+    #[payable]
+    pub fn remove_spend_cap(&mut self, token_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        require!(self.data_mut().spend_caps.remove(&token_id).is_some(), E005_INVALID_TOKEN);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::test::*;
+
+    #[test]
+    fn spend_caps() {
+        let (mut context, mut contract) = setup_contract();
+        contract.set_spend_cap(nusdt(), Some(U128(d(30, 6))), Some(U128(d(50, 6))), Some(60));
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(owner_id()).build());
+        let round_id = start_buyback(&mut contract, &mut context, nusdt(), d(30, 6));
+        let headroom = contract.get_spend_headroom(nusdt());
+        assert_eq!(headroom.max_amount_in, Some(U128(d(30, 6))));
+        assert_eq!(headroom.daily_amount_left, Some(U128(d(20, 6))));
+        assert_eq!(headroom.next_buyback_time, 1160);
+
+        // the part the exchange didn't use is given back to the daily cap
+        finish_buyback(&mut contract, &mut context, round_id, d(30, 6), Some(d(25, 6)), d(2, 18));
+        assert_eq!(contract.get_spend_headroom(nusdt()).daily_amount_left, Some(U128(d(25, 6))));
+
+        // the spend leaves the rolling window after 24 hours
+        testing_env!(context.block_timestamp(sec_to_nano(1100 + SPEND_WINDOW)).predecessor_account_id(owner_id()).build());
+        assert_eq!(contract.get_spend_headroom(nusdt()).daily_amount_left, Some(U128(d(50, 6))));
+    }
+
+    #[test]
+    #[should_panic(expected = "E116")]
+    fn spend_cap_max_amount_in() {
+        let (mut context, mut contract) = setup_contract();
+        contract.set_spend_cap(nusdt(), Some(U128(d(30, 6))), None, None);
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(owner_id()).build());
+        contract.do_buyback(swap_msg(nusdt(), d(31, 6)), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "E117")]
+    fn spend_cap_max_daily_amount() {
+        let (mut context, mut contract) = setup_contract();
+        contract.set_spend_cap(nusdt(), None, Some(U128(d(50, 6))), None);
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(owner_id()).build());
+        buyback(&mut contract, &mut context, nusdt(), d(30, 6), d(30, 6), d(3, 18));
+        testing_env!(context.predecessor_account_id(owner_id()).build());
+        contract.do_buyback(swap_msg(nusdt(), d(30, 6)), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "E118")]
+    fn spend_cap_min_buyback_gap() {
+        let (mut context, mut contract) = setup_contract();
+        contract.set_spend_cap(nusdt(), None, None, Some(60));
+        fund_round(&mut contract, &mut context, buyback_info(1000, 100, 10));
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(owner_id()).build());
+        buyback(&mut contract, &mut context, nusdt(), d(10, 6), d(10, 6), d(1, 18));
+        testing_env!(context.block_timestamp(sec_to_nano(1159)).predecessor_account_id(owner_id()).build());
+        contract.do_buyback(swap_msg(nusdt(), d(10, 6)), None);
+    }
+}
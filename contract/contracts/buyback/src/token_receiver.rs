@@ -10,6 +10,18 @@ pub enum TokenReceiverMessage {
         current_round_start_time: u32,
         total_buyback_time: u32,
         buyback_internal: u32,
+        #[serde(default)]
+        release_curve: ReleaseCurve,
+        /// Take over the unspent fund of the rounds before it once they have ended.
+        #[serde(default)]
+        roll_over_remainder: bool,
+        /// Max fund token amount per buyback token amount, scaled by PRICE_PRECISION, do_buyback never pays more.
+        #[serde(default)]
+        max_price: Option<U128>,
+    },
+    /// Add the deposit to the active round of the same fund token.
+    TopUp {
+        top_up_mode: TopUpMode,
     },
 }
 
@@ -29,24 +41,44 @@ impl FungibleTokenReceiver for Contract {
             self.data().guardians.contains(&sender_id), E002_NOT_ALLOWED);
         let token_id = env::predecessor_account_id();
 
-        if self.data().current_round_fund_amount != self.data().current_round_fund_cost {
-            env::panic_str(ERR101_BUYBACK_IN_PROGRESS);
-        }
-
         let message =
                 serde_json::from_str::<TokenReceiverMessage>(&msg).expect(ERR100_WRONG_MSG_FORMAT);
         match message {
-            TokenReceiverMessage::BuyBackInfo { current_round_start_time, total_buyback_time, buyback_internal } => {
+            TokenReceiverMessage::BuyBackInfo { current_round_start_time, total_buyback_time, buyback_internal, release_curve, roll_over_remainder, max_price } => {
                 require!(self.data().token_white_list.contains(&token_id), "Invalid token_id");
+                require!(amount.0 > 0, ERR105_INVALID_FUND_AMOUNT);
+                require!(max_price != Some(U128(0)), ERR134_INVALID_MAX_PRICE);
+                if let Err(err) = validate_schedule(
+                    current_round_start_time,
+                    total_buyback_time,
+                    buyback_internal,
+                    &release_curve,
+                    nano_to_sec(env::block_timestamp())
+                ) {
+                    env::panic_str(&err.to_string());
+                }
 
-                let contract_data = self.data_mut();
-                contract_data.total_buyback_time = total_buyback_time;
-                contract_data.buyback_internal = buyback_internal;
-
-                contract_data.current_round_start_time = current_round_start_time;
-                contract_data.current_round_fund_token_id = token_id;
-                contract_data.current_round_fund_amount = amount.0;
-                contract_data.current_round_fund_cost = 0;
+                let mut round = Round::new(
+                    token_id.clone(),
+                    amount.0,
+                    current_round_start_time,
+                    total_buyback_time,
+                    buyback_internal,
+                    release_curve
+                );
+                round.roll_over_remainder = roll_over_remainder;
+                round.max_price = max_price.map(|max_price| max_price.0);
+                let round_id = self.internal_queue_round(round);
+                log!("Buyback round {} queued with {} {}", round_id, amount.0, token_id);
+                self.internal_roll_over_ended_rounds(&token_id);
+            }
+            TokenReceiverMessage::TopUp { top_up_mode } => {
+                require!(amount.0 > 0, ERR105_INVALID_FUND_AMOUNT);
+                let round_id = self.internal_active_round_id(&token_id).expect(ERR103_NO_ACTIVE_ROUND);
+                let mut round = self.internal_get_round(round_id);
+                round.top_up(amount.0, &top_up_mode, nano_to_sec(env::block_timestamp()));
+                self.internal_set_round(round_id, &round);
+                events::emit::top_up_round(round_id, &token_id, amount.0, round.end_time());
             }
         }
 
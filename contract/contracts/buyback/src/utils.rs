@@ -5,6 +5,11 @@ uint::construct_uint!(
 );
 
 pub const MAX_RATIO: u32 = 10000;
+pub const DEFAULT_MAX_SLIPPAGE_BPS: u32 = 100;
+/// unit: sec
+pub const DEFAULT_MAX_PRICE_AGE: u32 = 600;
+/// Prices are fund token amount per buyback token amount, scaled by this.
+pub const PRICE_PRECISION: u128 = 10u128.pow(18);
 
 pub(crate) fn u128_ratio(a: u128, num: u128, denom: u128) -> Balance {
     (U256::from(a) * U256::from(num) / U256::from(denom)).as_u128()
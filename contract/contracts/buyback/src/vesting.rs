@@ -0,0 +1,260 @@
+use crate::*;
+use near_sdk::is_promise_success;
+
+pub const GAS_FOR_CLAIM_VESTED_CALLBACK: Gas = Gas(10 * Gas::ONE_TERA.0);
+
+/// A share distribute handed to a vesting recipient, released linearly from start_time.
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct VestingSchedule {
+    pub amount: u128,
+    // unit: sec
+    pub start_time: u32,
+    // unit: sec
+    pub duration: u32,
+}
+
+impl VestingSchedule {
+    pub fn vested_amount(&self, current_time: u32) -> u128 {
+        let pass_time = current_time.saturating_sub(self.start_time);
+        if pass_time >= self.duration {
+            self.amount
+        } else {
+            u128_ratio(self.amount, pass_time as u128, self.duration as u128)
+        }
+    }
+}
+
+/// The vesting schedules of a beneficiary and what it has claimed from them.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct Vesting {
+    pub schedules: Vec<VestingSchedule>,
+    // claimed from the schedules, including the claim being transferred
+    pub claimed: u128,
+    // claim being transferred, rolled back from claimed if the transfer fails
+    pub pending_claim: u128,
+}
+
+impl Vesting {
+    pub fn total_amount(&self) -> u128 {
+        self.schedules.iter().map(|schedule| schedule.amount).sum()
+    }
+
+    pub fn vested_amount(&self, current_time: u32) -> u128 {
+        self.schedules.iter().map(|schedule| schedule.vested_amount(current_time)).sum()
+    }
+
+    pub fn claimable_amount(&self, current_time: u32) -> u128 {
+        self.vested_amount(current_time).saturating_sub(self.claimed)
+    }
+
+    /// Drop the fully vested schedules already covered by claimed.
+    pub fn prune(&mut self, current_time: u32) {
+        let mut claimed = self.claimed;
+        self.schedules.retain(|schedule| {
+            let is_done = schedule.vested_amount(current_time) == schedule.amount && schedule.amount <= claimed;
+            if is_done {
+                claimed -= schedule.amount;
+            }
+            !is_done
+        });
+        self.claimed = claimed;
+    }
+
+    /// Merge a new share into the schedules, leaving at most a vested one and a vesting one.
+    /// What is still vesting of the earlier schedules vests along with the share,
+    /// until the end time of both weighted by their amounts.
+    pub fn add_schedule(&mut self, amount: u128, current_time: u32, duration: u32) {
+        self.prune(current_time);
+        let mut vested_amount = 0;
+        let mut vesting_amount = amount;
+        let mut weighted_end_time = U256::from(amount) * U256::from(current_time + duration);
+        for schedule in self.schedules.drain(..) {
+            let vested = schedule.vested_amount(current_time);
+            vested_amount += vested;
+            vesting_amount += schedule.amount - vested;
+            weighted_end_time += U256::from(schedule.amount - vested) * U256::from(schedule.start_time + schedule.duration);
+        }
+        if vested_amount > 0 {
+            self.schedules.push(VestingSchedule {
+                amount: vested_amount,
+                start_time: current_time,
+                duration: 0,
+            });
+        }
+        if vesting_amount > 0 {
+            let end_time = (weighted_end_time / U256::from(vesting_amount)).as_u32();
+            self.schedules.push(VestingSchedule {
+                amount: vesting_amount,
+                start_time: current_time,
+                duration: end_time - current_time,
+            });
+        }
+    }
+}
+
+impl Contract {
+    /// Lock amount for the beneficiary, vesting linearly over duration from now.
+    pub fn internal_add_vesting(&mut self, account_id: &AccountId, amount: u128, duration: u32) {
+        let mut vesting = self.data().vestings.get(account_id).unwrap_or_default();
+        vesting.add_schedule(amount, nano_to_sec(env::block_timestamp()), duration);
+        self.data_mut().vestings.insert(account_id, &vesting);
+        self.data_mut().total_vesting_amount += amount;
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Send the caller what has vested of its schedules and it hasn't claimed yet.
+    pub fn claim_vested(&mut self) {
+        self.assert_no_buyback_pending();
+        let account_id = env::predecessor_account_id();
+        let mut vesting = self.data().vestings.get(&account_id).expect(ERR147_NOTHING_VESTED);
+        require!(vesting.pending_claim == 0, ERR148_CLAIM_IN_PROGRESS);
+        let amount = vesting.claimable_amount(nano_to_sec(env::block_timestamp()));
+        require!(amount > 0, ERR147_NOTHING_VESTED);
+        vesting.claimed += amount;
+        vesting.pending_claim = amount;
+        // the claim stays in the balance and reserved from distribute until the transfer resolves
+        self.data_mut().vestings.insert(&account_id, &vesting);
+        self.data_mut().claiming_amount += amount;
+
+        ext_fungible_token::ext(self.data().buyback_token_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(
+                account_id.clone(),
+                U128(amount),
+                None
+            ).then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_CLAIM_VESTED_CALLBACK)
+                    .callback_claim_vested(account_id)
+            );
+    }
+
+    #[private]
+    pub fn callback_claim_vested(&mut self, account_id: AccountId) {
+        let mut vesting = self.data().vestings.get(&account_id).expect(ERR147_NOTHING_VESTED);
+        let amount = std::mem::take(&mut vesting.pending_claim);
+        self.data_mut().claiming_amount -= amount;
+        if is_promise_success() {
+            vesting.prune(nano_to_sec(env::block_timestamp()));
+            self.data_mut().total_vesting_amount -= amount;
+        } else {
+            vesting.claimed -= amount;
+            log!("Failed to send {} vested to {}", amount, account_id);
+        }
+        if vesting.schedules.is_empty() {
+            self.data_mut().vestings.remove(&account_id);
+        } else {
+            self.data_mut().vestings.insert(&account_id, &vesting);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::test::*;
+    use near_sdk::PromiseResult;
+
+    #[test]
+    fn vesting_prune() {
+        let mut vesting = Vesting {
+            schedules: vec![
+                VestingSchedule { amount: 100, start_time: 0, duration: 100 },
+                VestingSchedule { amount: 100, start_time: 50, duration: 100 },
+            ],
+            claimed: 0,
+            pending_claim: 0,
+        };
+        assert_eq!(vesting.vested_amount(100), 150);
+        vesting.claimed = 150;
+        vesting.prune(100);
+        assert_eq!((vesting.schedules.len(), vesting.claimed), (1, 50));
+        assert_eq!(vesting.claimable_amount(150), 50);
+    }
+
+    #[test]
+    fn vesting_merge() {
+        let mut vesting = Vesting::default();
+        vesting.add_schedule(100, 0, 100);
+        vesting.claimed = 50;
+        // the 50 still vesting until 100 and the new 150 until 150 vest together until 137
+        vesting.add_schedule(150, 50, 100);
+        assert_eq!(vesting.schedules.len(), 2);
+        assert_eq!((vesting.schedules[0].amount, vesting.schedules[0].duration), (50, 0));
+        assert_eq!((vesting.schedules[1].amount, vesting.schedules[1].start_time, vesting.schedules[1].duration), (200, 50, 87));
+        assert_eq!(vesting.claimable_amount(50), 0);
+        assert_eq!(vesting.total_amount(), 250);
+
+        // the claimed vested schedule is dropped, and the count stays bounded
+        vesting.claimed = 100;
+        for time in 60..100 {
+            vesting.add_schedule(10, time, 100);
+        }
+        assert_eq!(vesting.schedules.len(), 2);
+        assert_eq!(vesting.total_amount() - vesting.claimed, 250 + 400 - 100);
+        assert_eq!(vesting.vested_amount(1000), vesting.total_amount());
+    }
+
+    #[test]
+    fn vesting_recipient() {
+        let (mut context, mut contract) = setup_contract();
+        let mut recipients = recipient_configs(vec![(company_account_id(), 4000), (reward_account_id(), 6000)]);
+        recipients[0].delivery_mode = DeliveryMode::Vesting { duration: 100 };
+        contract.set_recipients(recipients);
+        testing_env!(context.block_timestamp(sec_to_nano(1000)).attached_deposit(0).build());
+        contract.distribute();
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(100, 18))).unwrap()));
+        contract.callback_to_distribute();
+        assert_eq!(contract.get_metadata().total_vesting_amount.0, d(40, 18));
+        callback_with_result(&context, PromiseResult::Successful(vec![]));
+        contract.callback_ft_transfer(reward_account_id(), U128(d(60, 18)));
+
+        testing_env!(context.block_timestamp(sec_to_nano(1050)).predecessor_account_id(company_account_id()).build());
+        let vesting = contract.get_vesting(company_account_id()).unwrap();
+        assert_eq!((vesting.vested_amount.0, vesting.locked_amount.0, vesting.claimable_amount.0), (d(20, 18), d(20, 18), d(20, 18)));
+        contract.claim_vested();
+        // the claim in flight is still in the balance and isn't distributed while it is sent
+        assert_eq!(contract.get_metadata().total_vesting_amount.0, d(40, 18));
+        testing_env!(context.predecessor_account_id(owner_id()).build());
+        contract.distribute();
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(40, 18))).unwrap()));
+        contract.callback_to_distribute();
+        assert_eq!(contract.get_metadata().delivering_amount.0, 0);
+        // a failed transfer puts the claim back, and the balance still covers it
+        callback_with_result(&context, PromiseResult::Failed);
+        contract.callback_claim_vested(company_account_id());
+        assert_eq!(contract.get_vesting(company_account_id()).unwrap().claimable_amount.0, d(20, 18));
+        assert_eq!(contract.get_metadata().total_vesting_amount.0, d(40, 18));
+        testing_env!(context.predecessor_account_id(company_account_id()).build());
+        contract.claim_vested();
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(vec![]));
+        contract.callback_claim_vested(company_account_id());
+        let vesting = contract.get_vesting(company_account_id()).unwrap();
+        assert_eq!((vesting.claimed_amount.0, vesting.claimable_amount.0), (d(20, 18), 0));
+        assert_eq!(contract.get_metadata().total_vesting_amount.0, d(20, 18));
+
+        // the still vesting 20 isn't distributed again
+        testing_env!(context.predecessor_account_id(owner_id()).build());
+        contract.distribute();
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(serde_json::to_vec(&U128(d(20, 18))).unwrap()));
+        contract.callback_to_distribute();
+        assert_eq!(contract.get_vesting(company_account_id()).unwrap().schedules.len(), 1);
+
+        testing_env!(context.block_timestamp(sec_to_nano(1100)).predecessor_account_id(company_account_id()).build());
+        contract.claim_vested();
+        context.predecessor_account_id(contract_account_id());
+        callback_with_result(&context, PromiseResult::Successful(vec![]));
+        contract.callback_claim_vested(company_account_id());
+        assert!(contract.get_vesting(company_account_id()).is_none());
+        assert_eq!(contract.get_metadata().total_vesting_amount.0, 0);
+    }
+}
@@ -1,4 +1,293 @@
 use crate::*;
+use std::collections::HashMap;
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Deserialize, Debug))]
+pub struct RoundInfo {
+    pub round_id: u64,
+    pub fund_token_id: AccountId,
+    pub fund_amount: U128,
+    pub fund_cost: U128,
+    pub fund_locked: U128,
+    pub released_at_start: U128,
+    pub start_time: u32,
+    pub end_time: u32,
+    pub total_buyback_time: u32,
+    pub buyback_internal: u32,
+    pub release_curve: ReleaseCurve,
+    pub status: RoundStatus,
+    pub paused_duration: u32,
+    pub roll_over_remainder: bool,
+    pub rolled_in_amount: U128,
+    pub rolled_over_amount: U128,
+    pub received_amount: U128,
+    pub executions: u32,
+    // fund spent per buyback token received, scaled by PRICE_PRECISION
+    pub average_price: Option<U128>,
+    pub closed_at: Option<u32>,
+    pub cancelled: bool,
+    pub max_price: Option<U128>,
+    pub last_quote_price: Option<U128>,
+    pub last_quote_time: Option<u32>,
+}
+
+impl RoundInfo {
+    pub fn new(round_id: u64, round: Round) -> Self {
+        Self {
+            round_id,
+            end_time: round.end_time(),
+            average_price: round.average_price().map(U128),
+            cancelled: round.status == RoundStatus::Cancelled,
+            fund_token_id: round.fund_token_id,
+            fund_amount: U128(round.fund_amount),
+            fund_cost: U128(round.fund_cost),
+            fund_locked: U128(round.fund_locked),
+            released_at_start: U128(round.released_at_start),
+            start_time: round.start_time,
+            total_buyback_time: round.total_buyback_time,
+            buyback_internal: round.buyback_internal,
+            release_curve: round.release_curve,
+            status: round.status,
+            paused_duration: round.paused_duration,
+            roll_over_remainder: round.roll_over_remainder,
+            rolled_in_amount: U128(round.rolled_in_amount),
+            rolled_over_amount: U128(round.rolled_over_amount),
+            received_amount: U128(round.received_amount),
+            executions: round.executions,
+            closed_at: round.closed_at,
+            max_price: round.max_price.map(U128),
+            last_quote_price: round.last_quote_price.map(U128),
+            last_quote_time: round.last_quote_time,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Deserialize, Debug))]
+pub struct ExecutionInfo {
+    pub execution_id: u64,
+    pub round_id: u64,
+    pub fund_token_id: AccountId,
+    pub amount_in: U128,
+    pub cost: U128,
+    pub received_amount: U128,
+    pub time: u32,
+    // fund spent per buyback token received, scaled by PRICE_PRECISION
+    pub price: Option<U128>,
+    // oracle price at the swap, scaled by PRICE_PRECISION
+    pub oracle_price: Option<U128>,
+    pub keeper_id: Option<AccountId>,
+    pub bounty: U128,
+}
+
+impl ExecutionInfo {
+    pub fn new(execution_id: u64, execution: Execution) -> Self {
+        Self {
+            execution_id,
+            round_id: execution.round_id,
+            fund_token_id: execution.fund_token_id,
+            amount_in: U128(execution.amount_in),
+            cost: U128(execution.cost),
+            price: if execution.received_amount > 0 {
+                Some(U128(u128_ratio(execution.cost, PRICE_PRECISION, execution.received_amount)))
+            } else {
+                None
+            },
+            received_amount: U128(execution.received_amount),
+            time: execution.time,
+            oracle_price: execution.oracle_price.map(U128),
+            keeper_id: execution.keeper_id,
+            bounty: U128(execution.bounty),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Deserialize, Debug))]
+pub struct SpendCapInfo {
+    pub max_amount_in: Option<U128>,
+    pub max_daily_amount: Option<U128>,
+    pub min_buyback_gap: u32,
+}
+
+impl From<SpendCap> for SpendCapInfo {
+    fn from(spend_cap: SpendCap) -> Self {
+        Self {
+            max_amount_in: spend_cap.max_amount_in.map(U128),
+            max_daily_amount: spend_cap.max_daily_amount.map(U128),
+            min_buyback_gap: spend_cap.min_buyback_gap,
+        }
+    }
+}
+
+/// How far each spend cap is from rejecting a do_buyback, None means unlimited.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Deserialize, Debug))]
+pub struct SpendHeadroom {
+    pub max_amount_in: Option<U128>,
+    pub daily_amount_left: Option<U128>,
+    pub daily_amount_spent: U128,
+    // unit: sec
+    pub next_buyback_time: u32,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Deserialize, Debug))]
+pub struct OraclePriceInfo {
+    pub multiplier: U128,
+    pub decimals: u8,
+    // unit: sec
+    pub timestamp: u32,
+    // neither older than max_price_age nor than the oracle holds it valid for
+    pub fresh: bool,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Deserialize, Debug))]
+pub struct RouteInfo {
+    pub route_id: u32,
+    pub fund_token_id: AccountId,
+    pub hops: Vec<RouteHop>,
+}
+
+impl RouteInfo {
+    pub fn new(route_id: u32, route_template: RouteTemplate) -> Self {
+        Self {
+            route_id,
+            fund_token_id: route_template.fund_token_id,
+            hops: route_template.hops,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Deserialize, Debug))]
+pub struct KeeperBountyInfo {
+    pub bounty_bps: u32,
+    pub max_bounty: U128,
+    pub min_amount_in: U128,
+}
+
+impl From<KeeperBounty> for KeeperBountyInfo {
+    fn from(keeper_bounty: KeeperBounty) -> Self {
+        Self {
+            bounty_bps: keeper_bounty.bounty_bps,
+            max_bounty: U128(keeper_bounty.max_bounty),
+            min_amount_in: U128(keeper_bounty.min_amount_in),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Deserialize, Debug))]
+pub struct KeeperStatsInfo {
+    pub keeper_id: AccountId,
+    pub tokens: Vec<KeeperTokenStats>,
+    pub last_execution_time: u32,
+}
+
+impl KeeperStatsInfo {
+    pub fn new(keeper_id: AccountId, keeper_stats: KeeperStats) -> Self {
+        Self {
+            keeper_id,
+            tokens: keeper_stats.tokens,
+            last_execution_time: keeper_stats.last_execution_time,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Deserialize, Debug))]
+pub struct RecipientInfo {
+    pub account_id: AccountId,
+    pub weight_bps: u32,
+    pub label: String,
+    pub delivery_mode: DeliveryMode,
+    pub owed_amount: U128,
+}
+
+impl From<&Recipient> for RecipientInfo {
+    fn from(recipient: &Recipient) -> Self {
+        Self {
+            account_id: recipient.account_id.clone(),
+            weight_bps: recipient.weight_bps,
+            label: recipient.label.clone(),
+            delivery_mode: recipient.delivery_mode.clone(),
+            owed_amount: U128(recipient.owed_amount),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Deserialize, Debug))]
+pub struct VestingScheduleInfo {
+    pub amount: U128,
+    pub start_time: u32,
+    pub duration: u32,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Deserialize, Debug))]
+pub struct VestingInfo {
+    pub account_id: AccountId,
+    pub schedules: Vec<VestingScheduleInfo>,
+    pub vested_amount: U128,
+    // not vested yet
+    pub locked_amount: U128,
+    pub claimed_amount: U128,
+    pub claimable_amount: U128,
+}
+
+impl VestingInfo {
+    pub fn new(account_id: AccountId, vesting: Vesting, current_time: u32) -> Self {
+        let vested_amount = vesting.vested_amount(current_time);
+        Self {
+            account_id,
+            vested_amount: U128(vested_amount),
+            locked_amount: U128(vesting.total_amount() - vested_amount),
+            claimed_amount: U128(vesting.claimed),
+            claimable_amount: U128(vesting.claimable_amount(current_time)),
+            schedules: vesting.schedules.into_iter().map(|schedule| VestingScheduleInfo {
+                amount: U128(schedule.amount),
+                start_time: schedule.start_time,
+                duration: schedule.duration,
+            }).collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Deserialize, Debug))]
+pub struct ClaimableInfo {
+    pub account_id: AccountId,
+    pub amount: U128,
+}
+
+/// Whether the max price of the active round currently keeps do_buyback from spending.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Deserialize, Debug))]
+pub struct PriceCeilingStatus {
+    pub round_id: u64,
+    pub max_price: U128,
+    // fresh oracle price, preferred over the last quote
+    pub oracle_price: Option<U128>,
+    pub last_quote_price: Option<U128>,
+    pub last_quote_time: Option<u32>,
+    pub blocking: bool,
+}
 
 #[derive(Serialize)]
 #[serde(crate = "near_sdk::serde")]
@@ -7,27 +296,29 @@ pub struct ContractMetadata {
     pub version: String,
     pub owner: AccountId,
     pub ref_exchange_id: AccountId,
-    pub burn_account_id: AccountId, 
-    pub company_account_id: AccountId, 
-    pub reward_account_id: AccountId,
     pub buyback_token_id: AccountId,
     pub token_white_list: Vec<AccountId>,
     pub guardians: Vec<AccountId>,
-    pub burn_rate: u32,
-    pub company_rate: u32,
-    pub reward_rate: u32,
     pub state: RunningState,
+    pub paused_at: Option<u32>,
+    pub pending_operation: Option<PendingOperation>,
+    pub max_slippage_bps: u32,
+    pub price_oracle_id: Option<AccountId>,
+    pub max_price_age: u32,
+    pub registered_routes_only: bool,
+    pub keeper_bounties: HashMap<AccountId, KeeperBountyInfo>,
+    pub pool_white_list: Vec<u64>,
+    pub min_pool_liquidity: HashMap<AccountId, U128>,
 
-    pub total_buyback_time: u32,
-    pub buyback_internal: u32,
-    pub current_round_start_time: u32,
-    pub current_round_fund_token_id: AccountId,
-    pub current_round_fund_amount: U128,
-    pub current_round_fund_cost: U128,
+    pub active_rounds: Vec<RoundInfo>,
+    pub round_queues: HashMap<AccountId, Vec<u64>>,
 
-    pub belong_burn_amount: U128,
-    pub belong_company_amount: U128,
-    pub belong_reward_amount: U128,
+    pub recipients: Vec<RecipientInfo>,
+    pub delivering_amount: U128,
+    pub total_burned: U128,
+    pub total_vesting_amount: U128,
+    pub total_claimable_amount: U128,
+    pub claiming_amount: U128,
 }
 
 #[near_bindgen]
@@ -40,31 +331,215 @@ impl Contract {
             version: env!("CARGO_PKG_VERSION").to_string(),
             owner: contract_data.owner_id.clone(),
             ref_exchange_id: contract_data.ref_exchange_id.clone(),
-            burn_account_id: contract_data.burn_account_id.clone(),
-            company_account_id: contract_data.company_account_id.clone(),
-            reward_account_id: contract_data.reward_account_id.clone(),
             buyback_token_id: contract_data.buyback_token_id.clone(),
             token_white_list: contract_data.token_white_list.to_vec(),
             guardians: contract_data.guardians.to_vec(),
-            burn_rate: contract_data.burn_rate,
-            company_rate: contract_data.company_rate,
-            reward_rate: contract_data.reward_rate,
             state: contract_data.state.clone(),
+            paused_at: contract_data.paused_at,
+            pending_operation: contract_data.pending_operation.clone(),
+            max_slippage_bps: contract_data.max_slippage_bps,
+            price_oracle_id: contract_data.price_oracle_id.clone(),
+            max_price_age: contract_data.max_price_age,
+            registered_routes_only: contract_data.registered_routes_only,
+            keeper_bounties: contract_data.keeper_bounties.iter()
+                .map(|(token_id, keeper_bounty)| (token_id, keeper_bounty.into()))
+                .collect(),
+            pool_white_list: contract_data.pool_white_list.to_vec(),
+            min_pool_liquidity: contract_data.min_pool_liquidity.iter()
+                .map(|(token_id, min_pool_liquidity)| (token_id, U128(min_pool_liquidity)))
+                .collect(),
+
+            active_rounds: self.get_active_rounds(),
+            round_queues: contract_data.round_queues.to_vec().into_iter().collect(),
+
+            recipients: self.get_recipients(),
+            delivering_amount: U128(contract_data.delivering_amount),
+            total_burned: U128(contract_data.total_burned),
+            total_vesting_amount: U128(contract_data.total_vesting_amount),
+            total_claimable_amount: U128(contract_data.total_claimable_amount),
+            claiming_amount: U128(contract_data.claiming_amount),
+        }
+    }
+
+    pub fn get_available_fund_amount(&self, token_id: AccountId) -> U128 {
+        U128(self.available_fund_amount(&token_id))
+    }
+
+    /// Return the round at the head of the token's queue, which do_buyback consumes
+    pub fn get_active_round(&self, token_id: AccountId) -> Option<RoundInfo> {
+        self.internal_active_round_id(&token_id)
+            .map(|round_id| RoundInfo::new(round_id, self.internal_get_round(round_id)))
+    }
+
+    /// Return the active round of every fund token
+    pub fn get_active_rounds(&self) -> Vec<RoundInfo> {
+        self.data().round_queues.values()
+            .map(|round_queue| RoundInfo::new(round_queue[0], self.internal_get_round(round_queue[0])))
+            .collect()
+    }
+
+    /// Return the rounds waiting behind the token's active round, in consumption order
+    pub fn get_pending_rounds(&self, token_id: AccountId) -> Vec<RoundInfo> {
+        self.data().round_queues.get(&token_id).unwrap_or_default().into_iter()
+            .skip(1)
+            .map(|round_id| RoundInfo::new(round_id, self.internal_get_round(round_id)))
+            .collect()
+    }
+
+    pub fn get_round(&self, round_id: u64) -> Option<RoundInfo> {
+        self.data().rounds.get(round_id).map(|round| RoundInfo::new(round_id, round))
+    }
+
+    /// Return all rounds with ids in [from_index, from_index + limit)
+    pub fn list_rounds(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<RoundInfo> {
+        let rounds = &self.data().rounds;
+        let from_index = from_index.unwrap_or(0);
+        let limit = limit.unwrap_or(rounds.len());
+        (from_index..std::cmp::min(rounds.len(), from_index.saturating_add(limit)))
+            .map(|round_id| RoundInfo::new(round_id, rounds.get(round_id).unwrap()))
+            .collect()
+    }
 
-            total_buyback_time: contract_data.total_buyback_time,
-            buyback_internal: contract_data.buyback_internal,
-            current_round_start_time: contract_data.current_round_start_time,
-            current_round_fund_token_id: contract_data.current_round_fund_token_id.clone(),
-            current_round_fund_amount: U128(contract_data.current_round_fund_amount),
-            current_round_fund_cost: U128(contract_data.current_round_fund_cost),
+    pub fn get_execution(&self, execution_id: u64) -> Option<ExecutionInfo> {
+        self.data().executions.get(execution_id).map(|execution| ExecutionInfo::new(execution_id, execution))
+    }
+
+    /// Return executions with ids in [from_index, from_index + limit)
+    pub fn list_executions(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<ExecutionInfo> {
+        let executions = &self.data().executions;
+        let from_index = from_index.unwrap_or(0);
+        let limit = limit.unwrap_or(executions.len());
+        (from_index..std::cmp::min(executions.len(), from_index.saturating_add(limit)))
+            .map(|execution_id| ExecutionInfo::new(execution_id, executions.get(execution_id).unwrap()))
+            .collect()
+    }
+
+    /// Return finished and cancelled rounds among the round ids in [from_index, from_index + limit)
+    pub fn get_finished_rounds(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<RoundInfo> {
+        let rounds = &self.data().rounds;
+        let from_index = from_index.unwrap_or(0);
+        let limit = limit.unwrap_or(rounds.len());
+        (from_index..std::cmp::min(rounds.len(), from_index.saturating_add(limit)))
+            .map(|round_id| (round_id, rounds.get(round_id).unwrap()))
+            .filter(|(_, round)| round.status != RoundStatus::Queued)
+            .map(|(round_id, round)| RoundInfo::new(round_id, round))
+            .collect()
+    }
 
-            belong_burn_amount: U128(contract_data.belong_burn_amount),
-            belong_company_amount: U128(contract_data.belong_company_amount),
-            belong_reward_amount: U128(contract_data.belong_reward_amount),
+    pub fn get_spend_cap(&self, token_id: AccountId) -> Option<SpendCapInfo> {
+        self.data().spend_caps.get(&token_id).map(|spend_cap| spend_cap.into())
+    }
+
+    pub fn get_spend_headroom(&self, token_id: AccountId) -> SpendHeadroom {
+        let spend_cap = self.data().spend_caps.get(&token_id).unwrap_or_default();
+        let spend_record = self.internal_get_spend_record(&token_id);
+        let daily_amount_spent = spend_record.daily_amount();
+        SpendHeadroom {
+            max_amount_in: spend_cap.max_amount_in.map(U128),
+            daily_amount_left: spend_cap.max_daily_amount.map(|amount| U128(amount.saturating_sub(daily_amount_spent))),
+            daily_amount_spent: U128(daily_amount_spent),
+            next_buyback_time: spend_record.last_buyback_time
+                .map(|time| time.saturating_add(spend_cap.min_buyback_gap))
+                .unwrap_or(0),
         }
     }
 
-    pub fn get_available_fund_amount(&self) -> U128 {
-        U128(self.available_fund_amount())
+    /// Return the cached oracle price of the token
+    pub fn get_oracle_price(&self, token_id: AccountId) -> Option<OraclePriceInfo> {
+        self.data().oracle_prices.get(&token_id).map(|price| OraclePriceInfo {
+            fresh: price.is_fresh(nano_to_sec(env::block_timestamp()), self.data().max_price_age),
+            multiplier: U128(price.multiplier),
+            decimals: price.decimals,
+            timestamp: price.timestamp,
+        })
     }
-}
\ No newline at end of file
+
+    /// Return fund token per buyback token implied by the oracle, scaled by PRICE_PRECISION, None unless both prices are fresh
+    pub fn get_oracle_fund_price(&self, token_id: AccountId) -> Option<U128> {
+        self.internal_current_oracle_price(&token_id).map(U128)
+    }
+
+    /// Return the max price of the token's active round and whether the current price is above it,
+    /// None if the active round has no max price
+    pub fn get_price_ceiling_status(&self, token_id: AccountId) -> Option<PriceCeilingStatus> {
+        let round_id = self.internal_active_round_id(&token_id)?;
+        let round = self.internal_get_round(round_id);
+        let max_price = round.max_price?;
+        let oracle_price = self.internal_current_oracle_price(&token_id);
+        Some(PriceCeilingStatus {
+            round_id,
+            max_price: U128(max_price),
+            blocking: matches!(oracle_price.or(round.last_quote_price), Some(price) if price > max_price),
+            oracle_price: oracle_price.map(U128),
+            last_quote_price: round.last_quote_price.map(U128),
+            last_quote_time: round.last_quote_time,
+        })
+    }
+
+    pub fn get_route(&self, route_id: u32) -> Option<RouteInfo> {
+        self.data().route_templates.get(&route_id).map(|route_template| RouteInfo::new(route_id, route_template))
+    }
+
+    /// Return the registered routes, only those of fund_token_id if given
+    pub fn list_routes(&self, fund_token_id: Option<AccountId>) -> Vec<RouteInfo> {
+        self.data().route_templates.iter()
+            .filter(|(_, route_template)| match &fund_token_id {
+                Some(token_id) => *token_id == route_template.fund_token_id,
+                None => true,
+            })
+            .map(|(route_id, route_template)| RouteInfo::new(route_id, route_template))
+            .collect()
+    }
+
+    pub fn get_keeper_bounty(&self, token_id: AccountId) -> Option<KeeperBountyInfo> {
+        self.data().keeper_bounties.get(&token_id).map(|keeper_bounty| keeper_bounty.into())
+    }
+
+    pub fn get_keeper_stats(&self, keeper_id: AccountId) -> Option<KeeperStatsInfo> {
+        self.data().keeper_stats.get(&keeper_id).map(|keeper_stats| KeeperStatsInfo::new(keeper_id, keeper_stats))
+    }
+
+    /// Return the stats of keepers in [from_index, from_index + limit) of the keeper list
+    pub fn list_keepers(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<KeeperStatsInfo> {
+        let keeper_stats = &self.data().keeper_stats;
+        let from_index = from_index.unwrap_or(0);
+        let limit = limit.unwrap_or(keeper_stats.len());
+        keeper_stats.iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .map(|(keeper_id, keeper_stats)| KeeperStatsInfo::new(keeper_id, keeper_stats))
+            .collect()
+    }
+
+    /// Return the recipients of distribute in order, with what each is still owed
+    pub fn get_recipients(&self) -> Vec<RecipientInfo> {
+        self.data().recipients.iter().map(|recipient| recipient.into()).collect()
+    }
+
+    /// Return the buyback token burned by distribute so far
+    pub fn get_total_burned(&self) -> U128 {
+        U128(self.data().total_burned)
+    }
+
+    /// Return the vesting schedules of a beneficiary with how much of them has vested and is still locked
+    pub fn get_vesting(&self, account_id: AccountId) -> Option<VestingInfo> {
+        self.data().vestings.get(&account_id)
+            .map(|vesting| VestingInfo::new(account_id, vesting, nano_to_sec(env::block_timestamp())))
+    }
+
+    pub fn get_claimable_amount(&self, account_id: AccountId) -> U128 {
+        U128(self.data().claimable_amounts.get(&account_id).unwrap_or(0))
+    }
+
+    /// Return what the accounts in [from_index, from_index + limit) of the claimable list can claim
+    pub fn list_claimable_amounts(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<ClaimableInfo> {
+        let claimable_amounts = &self.data().claimable_amounts;
+        let from_index = from_index.unwrap_or(0);
+        let limit = limit.unwrap_or(claimable_amounts.len());
+        claimable_amounts.iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .map(|(account_id, amount)| ClaimableInfo { account_id, amount: U128(amount) })
+            .collect()
+    }
+}
@@ -36,6 +36,40 @@ impl BuyBackContract {
             .await
     }
 
+    pub async fn extend_pool_white_list(
+        &self,
+        caller: &Account,
+        pool_ids: Vec<u64>
+    ) -> Result<ExecutionFinalResult> {
+        caller
+            .call(self.0.id(), "extend_pool_white_list")
+            .args_json(json!({
+                "pool_ids": pool_ids,
+            }))
+            .gas(20_000_000_000_000)
+            .deposit(1)
+            .transact()
+            .await
+    }
+
+    pub async fn set_min_pool_liquidity(
+        &self,
+        caller: &Account,
+        token_id: &AccountId,
+        min_pool_liquidity: Option<u128>
+    ) -> Result<ExecutionFinalResult> {
+        caller
+            .call(self.0.id(), "set_min_pool_liquidity")
+            .args_json(json!({
+                "token_id": token_id,
+                "min_pool_liquidity": min_pool_liquidity.map(U128),
+            }))
+            .gas(20_000_000_000_000)
+            .deposit(1)
+            .transact()
+            .await
+    }
+
     pub async fn change_ref_exchange_id(
         &self,
         caller: &Account,
@@ -77,6 +111,103 @@ impl BuyBackContract {
             .await
     }
 
+    pub async fn simulate_buyback(
+        &self,
+        caller: &Account,
+        swap_msg: String
+    ) -> Result<BuybackSimulation> {
+        caller
+            .call(self.0.id(), "simulate_buyback")
+            .args_json(json!({
+                "swap_msg": swap_msg,
+            }))
+            .max_gas()
+            .transact()
+            .await?
+            .json::<BuybackSimulation>()
+    }
+
+    pub async fn register_route(
+        &self,
+        caller: &Account,
+        fund_token_id: &AccountId,
+        hops: Vec<(u64, &AccountId)>
+    ) -> Result<ExecutionFinalResult> {
+        caller
+            .call(self.0.id(), "register_route")
+            .args_json(json!({
+                "fund_token_id": fund_token_id,
+                "hops": hops.into_iter().map(|(pool_id, token_out)| json!({
+                    "pool_id": pool_id,
+                    "token_out": token_out,
+                })).collect::<Vec<_>>(),
+            }))
+            .gas(20_000_000_000_000)
+            .deposit(1)
+            .transact()
+            .await
+    }
+
+    pub async fn execute_registered_buyback(
+        &self,
+        caller: &Account,
+        route_id: u32,
+        amount_in: u128,
+        min_amount_out: u128
+    ) -> Result<ExecutionFinalResult> {
+        caller
+            .call(self.0.id(), "execute_registered_buyback")
+            .args_json(json!({
+                "route_id": route_id,
+                "amount_in": U128(amount_in),
+                "min_amount_out": U128(min_amount_out),
+            }))
+            .max_gas()
+            .transact()
+            .await
+    }
+
+    pub async fn set_keeper_bounty(
+        &self,
+        caller: &Account,
+        token_id: &AccountId,
+        bounty_bps: u32,
+        max_bounty: u128,
+        min_amount_in: u128
+    ) -> Result<ExecutionFinalResult> {
+        caller
+            .call(self.0.id(), "set_keeper_bounty")
+            .args_json(json!({
+                "token_id": token_id,
+                "bounty_bps": bounty_bps,
+                "max_bounty": U128(max_bounty),
+                "min_amount_in": U128(min_amount_in),
+            }))
+            .max_gas()
+            .deposit(1)
+            .transact()
+            .await
+    }
+
+    pub async fn keeper_buyback(
+        &self,
+        caller: &Account,
+        route_id: u32,
+        amount_in: u128,
+        min_amount_out: u128
+    ) -> Result<ExecutionFinalResult> {
+        caller
+            .call(self.0.id(), "keeper_buyback")
+            .args_json(json!({
+                "route_id": route_id,
+                "amount_in": U128(amount_in),
+                "min_amount_out": U128(min_amount_out),
+            }))
+            .max_gas()
+            .transact()
+            .await
+    }
+
     pub async fn distribute(
         &self,
         caller: &Account,
@@ -88,19 +219,43 @@ impl BuyBackContract {
             .await
     }
     
-    pub async fn change_buyback_rate(
+    pub async fn claim_vested(
         &self,
         caller: &Account,
-        burn_rate: u32, 
-        company_rate: u32, 
-        reward_rate: u32
     ) -> Result<ExecutionFinalResult> {
         caller
-            .call(self.0.id(), "change_buyback_rate")
+            .call(self.0.id(), "claim_vested")
+            .max_gas()
+            .transact()
+            .await
+    }
+
+    pub async fn claim(
+        &self,
+        caller: &Account,
+    ) -> Result<ExecutionFinalResult> {
+        caller
+            .call(self.0.id(), "claim")
+            .max_gas()
+            .transact()
+            .await
+    }
+
+    pub async fn set_recipients(
+        &self,
+        caller: &Account,
+        recipients: Vec<(&AccountId, u32, &str, serde_json::Value)>,
+    ) -> Result<ExecutionFinalResult> {
+        let recipients: Vec<_> = recipients.into_iter().map(|(account_id, weight_bps, label, delivery_mode)| json!({
+            "account_id": account_id,
+            "weight_bps": weight_bps,
+            "label": label,
+            "delivery_mode": delivery_mode,
+        })).collect();
+        caller
+            .call(self.0.id(), "set_recipients")
             .args_json(json!({
-                "burn_rate": burn_rate, 
-                "company_rate": company_rate, 
-                "reward_rate": reward_rate
+                "recipients": recipients,
             }))
             .max_gas()
             .deposit(1)
@@ -109,6 +264,35 @@ impl BuyBackContract {
     }
 }
 
+impl BuyBackContract {
+    pub async fn set_price_oracle(
+        &self,
+        caller: &Account,
+        price_oracle_id: Option<&AccountId>
+    ) -> Result<ExecutionFinalResult> {
+        caller
+            .call(self.0.id(), "set_price_oracle")
+            .args_json(json!({
+                "price_oracle_id": price_oracle_id,
+            }))
+            .gas(20_000_000_000_000)
+            .deposit(1)
+            .transact()
+            .await
+    }
+
+    pub async fn refresh_oracle_prices(
+        &self,
+        caller: &Account,
+    ) -> Result<ExecutionFinalResult> {
+        caller
+            .call(self.0.id(), "refresh_oracle_prices")
+            .max_gas()
+            .transact()
+            .await
+    }
+}
+
 impl BuyBackContract {
     pub async fn get_metadata(
         &self,
@@ -120,16 +304,79 @@ impl BuyBackContract {
             .json::<ContractMetadata>()
     }
 
+    pub async fn get_vesting(
+        &self,
+        account_id: &AccountId,
+    ) -> Result<Option<VestingInfo>> {
+        self.0
+            .call("get_vesting")
+            .args_json(json!({
+                "account_id": account_id,
+            }))
+            .view()
+            .await?
+            .json::<Option<VestingInfo>>()
+    }
+
+    pub async fn list_claimable_amounts(
+        &self,
+    ) -> Result<Vec<ClaimableInfo>> {
+        self.0
+            .call("list_claimable_amounts")
+            .view()
+            .await?
+            .json::<Vec<ClaimableInfo>>()
+    }
+
+    pub async fn get_total_burned(
+        &self,
+    ) -> Result<U128> {
+        self.0
+            .call("get_total_burned")
+            .view()
+            .await?
+            .json::<U128>()
+    }
+
     pub async fn get_available_fund_amount(
         &self,
+        token_id: &AccountId,
     ) -> Result<U128> {
         self.0
             .call("get_available_fund_amount")
-            // .args_json(json!({
-            // }))
+            .args_json(json!({
+                "token_id": token_id,
+            }))
             .view()
             .await?
             .json::<U128>()
     }
-    
-}
\ No newline at end of file
+
+    pub async fn get_oracle_price(
+        &self,
+        token_id: &AccountId,
+    ) -> Result<Option<OraclePriceInfo>> {
+        self.0
+            .call("get_oracle_price")
+            .args_json(json!({
+                "token_id": token_id,
+            }))
+            .view()
+            .await?
+            .json::<Option<OraclePriceInfo>>()
+    }
+
+    pub async fn get_keeper_stats(
+        &self,
+        keeper_id: &AccountId,
+    ) -> Result<Option<KeeperStatsInfo>> {
+        self.0
+            .call("get_keeper_stats")
+            .args_json(json!({
+                "keeper_id": keeper_id,
+            }))
+            .view()
+            .await?
+            .json::<Option<KeeperStatsInfo>>()
+    }
+}
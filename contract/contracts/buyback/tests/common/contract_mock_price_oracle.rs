@@ -0,0 +1,53 @@
+use mock_price_oracle::{Price, PriceData};
+
+use crate::*;
+
+pub struct PriceOracleContract(pub Contract);
+
+impl PriceOracleContract {
+    pub async fn set_price(
+        &self,
+        caller: &Account,
+        asset_id: &AccountId,
+        price: Option<(u128, u8)>,
+    ) -> Result<ExecutionFinalResult> {
+        caller
+            .call(self.0.id(), "set_price")
+            .args_json(json!({
+                "asset_id": asset_id,
+                "price": price.map(|(multiplier, decimals)| Price { multiplier: U128(multiplier), decimals }),
+            }))
+            .gas(20_000_000_000_000)
+            .transact()
+            .await
+    }
+
+    pub async fn set_timestamp(
+        &self,
+        caller: &Account,
+        timestamp: Option<Timestamp>,
+    ) -> Result<ExecutionFinalResult> {
+        caller
+            .call(self.0.id(), "set_timestamp")
+            .args_json(json!({
+                "timestamp": timestamp.map(U64),
+            }))
+            .gas(20_000_000_000_000)
+            .transact()
+            .await
+    }
+
+    pub async fn get_price_data(
+        &self,
+        asset_ids: Option<Vec<&AccountId>>,
+    ) -> Result<PriceData> {
+        self.0
+            .call("get_price_data")
+            .args_json(json!({
+                "asset_ids": asset_ids,
+            }))
+            .view()
+            .await?
+            .json::<PriceData>()
+    }
+}
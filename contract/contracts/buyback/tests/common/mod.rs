@@ -19,10 +19,12 @@ mod setup;
 mod contract_buyback;
 mod contract_mock_ft;
 mod contract_mock_ref_exchange;
+mod contract_mock_price_oracle;
 mod utils;
 
 pub use setup::*;
 pub use contract_buyback::*;
 pub use contract_mock_ft::*;
 pub use contract_mock_ref_exchange::*;
+pub use contract_mock_price_oracle::*;
 pub use utils::*;
@@ -1,9 +1,11 @@
 use crate::*;
 
-pub const PREVIOUS_BUYBACK_WASM: &str = "../../res/buyback.wasm";
+// built by `make build-previous-release`
+pub const PREVIOUS_BUYBACK_WASM: &str = "../../releases/buyback_release_v0.1.0.wasm";
 pub const BUYBACK_WASM: &str = "../../res/buyback.wasm";
 const REF_EXCHANGE_WASM: &str = "../../res/mock_ref_exchange.wasm";
 const FT_WASM: &str = "../../res/mock_ft.wasm";
+const PRICE_ORACLE_WASM: &str = "../../res/mock_price_oracle.wasm";
 
 pub async fn deploy_buyback(
     root: &Account,
@@ -127,4 +129,28 @@ pub async fn deploy_ref_exchange(
         .await?
         .is_success());
     Ok(RefExchange(ref_exchange))
-}
\ No newline at end of file
+}
+
+pub async fn deploy_mock_price_oracle(
+    root: &Account,
+) -> Result<PriceOracleContract> {
+    let price_oracle = root
+        .create_subaccount("price_oracle")
+        .initial_balance(parse_near!("50 N"))
+        .transact()
+        .await?
+        .unwrap();
+    let price_oracle = price_oracle
+        .deploy(&std::fs::read(PRICE_ORACLE_WASM).unwrap())
+        .await?
+        .unwrap();
+    assert!(price_oracle.call("new")
+        .args_json(json!({
+            "recency_duration_sec": 90,
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .is_success());
+    Ok(PriceOracleContract(price_oracle))
+}
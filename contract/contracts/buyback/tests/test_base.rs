@@ -51,24 +51,42 @@ async fn test_base() -> Result<()> {
         check!(ref_exchange_contract.deposit(&brrr_token_contract, &alice, 10000 * 10u128.pow(18)));
         check!(ref_exchange_contract.add_liquidity(&alice, 0, vec![U128(10000 * 10u128.pow(6)), U128(10000 * 10u128.pow(18))], None));
         check!(view ref_exchange_contract.get_pool(0));
+        check!(buyback_contract.register_route(&owner, usdt_token_contract.0.id(), vec![(0, brrr_token_contract.0.id())]));
+        // only the pool above, and only while it holds at least 1000 usdt
+        check!(buyback_contract.extend_pool_white_list(&owner, vec![0]));
+        check!(buyback_contract.set_min_pool_liquidity(&owner, usdt_token_contract.0.id(), Some(1000 * 10u128.pow(6))));
     }
     
+    let price_oracle_contract = deploy_mock_price_oracle(&root).await?;
+    {
+        // the pool trades 1 usdt for 1 brrr, the oracle prices brrr a bit higher
+        check!(price_oracle_contract.set_price(&root, usdt_token_contract.0.id(), Some((10000, 10))));
+        check!(price_oracle_contract.set_price(&root, brrr_token_contract.0.id(), Some((10200, 22))));
+        check!(buyback_contract.set_price_oracle(&owner, Some(price_oracle_contract.0.id())));
+        check!(buyback_contract.refresh_oracle_prices(&guardian));
+        check!(view buyback_contract.get_oracle_price(brrr_token_contract.0.id()));
+    }
+
     assert!(usdt_token_contract.ft_mint(&root, &owner, 10000 * 10u128.pow(6)).await?.is_success());
 
     let current_timestamp = nano_to_sec(worker.view_block().await?.timestamp());
     let msg = serde_json::to_string(&TokenReceiverMessage::BuyBackInfo {
         current_round_start_time: current_timestamp,
         total_buyback_time: 100,
-        buyback_internal: 10
+        buyback_internal: 10,
+        release_curve: ReleaseCurve::Stepwise,
+        roll_over_remainder: false,
+        max_price: None,
     }).unwrap();
     check!(buyback_contract.init_buyback_round(&usdt_token_contract, &owner, 10000 * 10u128.pow(6), msg));
     let mut available_fund_amount = 0;
     while available_fund_amount == 0 {
         worker.fast_forward(10).await?;
-        available_fund_amount = buyback_contract.get_available_fund_amount().await?.0;
+        available_fund_amount = buyback_contract.get_available_fund_amount(usdt_token_contract.0.id()).await?.0;
     }
     println!("{:?}", available_fund_amount);
 
+    let min_amount_out = ref_exchange_contract.get_return(0, usdt_token_contract.0.id(), available_fund_amount, brrr_token_contract.0.id()).await?.0 * 99 / 100;
     let swap_msg = serde_json::to_string(&SwapMessage::Execute {
         referral_id: None,
         actions: vec![
@@ -78,12 +96,17 @@ async fn test_base() -> Result<()> {
                     token_in: near_sdk::AccountId::new_unchecked(usdt_token_contract.0.id().to_string()),
                     amount_in: Some(U128(available_fund_amount)),
                     token_out: near_sdk::AccountId::new_unchecked(brrr_token_contract.0.id().to_string()),
-                    min_amount_out: U128(0),
+                    min_amount_out: U128(min_amount_out),
                 }
             )
         ]
     }).unwrap();
     check!(view ref_exchange_contract.get_return(0, usdt_token_contract.0.id(), available_fund_amount, brrr_token_contract.0.id()));
+    // the dry run quotes the same as the exchange
+    let simulation = buyback_contract.simulate_buyback(&guardian, swap_msg.clone()).await?;
+    println!("{:?}", simulation);
+    assert!(simulation.failed_checks.is_empty());
+    assert_eq!(simulation.expected_amount_out.unwrap().0 * 99 / 100, min_amount_out);
     check!(view "brrr buyback_contract balance" brrr_token_contract.ft_balance_of(&buyback_contract.0.as_account()));
     check!(view "usdt buyback_contract balance" usdt_token_contract.ft_balance_of(&buyback_contract.0.as_account()));
     check!(logs buyback_contract.do_buyback(&guardian, swap_msg));
@@ -95,8 +118,14 @@ async fn test_base() -> Result<()> {
     check!(view "brrr burn balance" brrr_token_contract.ft_balance_of(&burn));
     check!(view "brrr company balance" brrr_token_contract.ft_balance_of(&company));
     check!(view "brrr reward alance" brrr_token_contract.ft_balance_of(&reward));
-    check!(buyback_contract.change_buyback_rate(&guardian, 2000, 2000, 6000));
+    check!(buyback_contract.set_recipients(&guardian, vec![
+        (burn.id(), 2000, "burn", json!("Burn")),
+        (company.id(), 2000, "company", json!("Transfer")),
+        (reward.id(), 6000, "reward", json!("Transfer")),
+    ]));
     check!(print buyback_contract.distribute(&guardian));
+    // the burn share is burned from the supply rather than sent to the burn account
+    assert!(buyback_contract.get_total_burned().await?.0 > 0);
     check!(view "brrr burn balance" brrr_token_contract.ft_balance_of(&burn));
     check!(view "brrr company balance" brrr_token_contract.ft_balance_of(&company));
     check!(view "brrr reward alance" brrr_token_contract.ft_balance_of(&reward));
@@ -116,28 +145,36 @@ async fn test_base() -> Result<()> {
     available_fund_amount = 0;
     while available_fund_amount == 0 {
         worker.fast_forward(10).await?;
-        available_fund_amount = buyback_contract.get_available_fund_amount().await?.0;
+        available_fund_amount = buyback_contract.get_available_fund_amount(usdt_token_contract.0.id()).await?.0;
     }
 
-    let swap_msg = serde_json::to_string(&SwapMessage::Execute {
-        referral_id: None,
-        actions: vec![
-            Action::Swap (
-                SwapAction {
-                    pool_id: 0,
-                    token_in: near_sdk::AccountId::new_unchecked(usdt_token_contract.0.id().to_string()),
-                    amount_in: Some(U128(available_fund_amount)),
-                    token_out: near_sdk::AccountId::new_unchecked(brrr_token_contract.0.id().to_string()),
-                    min_amount_out: U128(0),
-                }
-            )
-        ]
-    }).unwrap();
+    let min_amount_out = ref_exchange_contract.get_return(0, usdt_token_contract.0.id(), available_fund_amount, brrr_token_contract.0.id()).await?.0 * 99 / 100;
 
     check!(view ref_exchange_contract.get_return(0, usdt_token_contract.0.id(), available_fund_amount, brrr_token_contract.0.id()));
-    check!(logs buyback_contract.do_buyback(&guardian, swap_msg));
+    check!(buyback_contract.refresh_oracle_prices(&guardian));
+    // the swaps are built by the contract from the registered route
+    check!(logs buyback_contract.execute_registered_buyback(&guardian, 0, available_fund_amount, min_amount_out));
     check!(print buyback_contract.distribute(&guardian));
 
+    // anyone may buy back along the registered route once the fund token has a keeper bounty
+    check!(buyback_contract.set_keeper_bounty(&owner, usdt_token_contract.0.id(), 10, 10u128.pow(6), 1));
+    available_fund_amount = 0;
+    while available_fund_amount == 0 {
+        worker.fast_forward(10).await?;
+        available_fund_amount = buyback_contract.get_available_fund_amount(usdt_token_contract.0.id()).await?.0;
+    }
+    // leave room in the released fund for the bounty
+    let amount_in = available_fund_amount * 9 / 10;
+    let min_amount_out = ref_exchange_contract.get_return(0, usdt_token_contract.0.id(), amount_in, brrr_token_contract.0.id()).await?.0 * 99 / 100;
+    let bounty = std::cmp::min(amount_in / 1000, 10u128.pow(6));
+    let alice_usdt_before = usdt_token_contract.ft_balance_of(&alice).await?.0;
+    check!(buyback_contract.refresh_oracle_prices(&alice));
+    check!(logs buyback_contract.keeper_buyback(&alice, 0, amount_in, min_amount_out));
+    assert_eq!(usdt_token_contract.ft_balance_of(&alice).await?.0, alice_usdt_before + bounty);
+    let keeper_stats = buyback_contract.get_keeper_stats(alice.id()).await?.unwrap();
+    assert_eq!(keeper_stats.tokens[0].executions, 1);
+    assert_eq!(keeper_stats.tokens[0].bounty_paid.0, bounty);
+
     check!(view "brrr buyback_contract balance" brrr_token_contract.ft_balance_of(&buyback_contract.0.as_account()));
     check!(view buyback_contract.get_metadata());
 
@@ -3,7 +3,7 @@ mod common;
 use crate::common::*;
 
 const PREVIOUS_VERSION: &'static str = "0.1.0";
-const LATEST_VERSION: &'static str = "0.1.0";
+const LATEST_VERSION: &'static str = "0.2.0";
 
 #[tokio::test]
 async fn test_upgrade() -> Result<()> {
@@ -18,8 +18,13 @@ async fn test_upgrade() -> Result<()> {
     let brrr_token_contract = deploy_mock_ft(&root, "brrr", 18).await?;
 
     let previous_burrowland_contract = deploy_previous_version_buyback(&root, &owner, &burn, &company, &reward, brrr_token_contract.0.as_account()).await?;
-    let metadata = previous_burrowland_contract.get_metadata().await?;
-    assert_eq!(metadata.version, PREVIOUS_VERSION);
+    // the metadata of 0.1.0 has a different shape, only its version is read
+    let metadata = previous_burrowland_contract.0
+        .call("get_metadata")
+        .view()
+        .await?
+        .json::<serde_json::Value>()?;
+    assert_eq!(metadata["version"], PREVIOUS_VERSION);
 
     assert!(owner
         .call(previous_burrowland_contract.0.id(), "upgrade")
@@ -29,5 +34,10 @@ async fn test_upgrade() -> Result<()> {
         .await?.is_success());
     let metadata = previous_burrowland_contract.get_metadata().await?;
     assert_eq!(metadata.version, LATEST_VERSION);
+    assert_eq!(metadata.recipients.len(), 3);
+    assert_eq!(metadata.recipients[0].account_id.to_string(), burn.id().to_string());
+    assert_eq!(metadata.recipients[1].account_id.to_string(), company.id().to_string());
+    assert_eq!(metadata.recipients[2].account_id.to_string(), reward.id().to_string());
+    assert!(!metadata.registered_routes_only);
     Ok(())
 }
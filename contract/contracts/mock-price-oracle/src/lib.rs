@@ -0,0 +1,108 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::UnorderedMap;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, PanicOnDefault};
+
+pub type AssetId = String;
+
+/// USD value of one smallest unit of the asset is multiplier / 10^decimals.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct Price {
+    pub multiplier: U128,
+    pub decimals: u8,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct AssetOptionalPrice {
+    pub asset_id: AssetId,
+    pub price: Option<Price>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct PriceData {
+    pub timestamp: U64,
+    pub recency_duration_sec: u32,
+    pub prices: Vec<AssetOptionalPrice>,
+}
+
+#[near_bindgen]
+#[derive(BorshSerialize, BorshDeserialize, PanicOnDefault)]
+pub struct Contract {
+    prices: UnorderedMap<AssetId, Price>,
+    recency_duration_sec: u32,
+    // reported instead of the block timestamp when set, to simulate stale prices. unit: nano sec
+    timestamp: Option<u64>,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[init]
+    pub fn new(recency_duration_sec: u32) -> Self {
+        Self {
+            prices: UnorderedMap::new(b"p".to_vec()),
+            recency_duration_sec,
+            timestamp: None,
+        }
+    }
+
+    /// Set the price of an asset, None removes it.
+    pub fn set_price(&mut self, asset_id: AssetId, price: Option<Price>) {
+        match price {
+            Some(price) => self.prices.insert(&asset_id, &price),
+            None => self.prices.remove(&asset_id),
+        };
+    }
+
+    /// Report price data at this time instead of the block timestamp, None goes back to the block timestamp.
+    pub fn set_timestamp(&mut self, timestamp: Option<U64>) {
+        self.timestamp = timestamp.map(|timestamp| timestamp.0);
+    }
+
+    pub fn get_price_data(&self, asset_ids: Option<Vec<AssetId>>) -> PriceData {
+        let asset_ids = asset_ids.unwrap_or_else(|| self.prices.keys().collect());
+        PriceData {
+            timestamp: U64(self.timestamp.unwrap_or_else(env::block_timestamp)),
+            recency_duration_sec: self.recency_duration_sec,
+            prices: asset_ids
+                .into_iter()
+                .map(|asset_id| AssetOptionalPrice {
+                    price: self.prices.get(&asset_id),
+                    asset_id,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use super::*;
+
+    #[test]
+    fn test_basics() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.block_timestamp(100).build());
+        let mut contract = Contract::new(90);
+        contract.set_price("nusdt".to_string(), Some(Price { multiplier: U128(10000), decimals: 10 }));
+        let price_data = contract.get_price_data(Some(vec!["nusdt".to_string(), "brrr".to_string()]));
+        assert_eq!(price_data.timestamp.0, 100);
+        assert_eq!(price_data.prices[0].price.as_ref().unwrap().multiplier.0, 10000);
+        assert!(price_data.prices[1].price.is_none());
+
+        contract.set_timestamp(Some(U64(50)));
+        contract.set_price("nusdt".to_string(), None);
+        let price_data = contract.get_price_data(None);
+        assert_eq!(price_data.timestamp.0, 50);
+        assert!(price_data.prices.is_empty());
+    }
+}